@@ -1,45 +1,112 @@
-use std::{error::Error, io::stdout};
+use std::{
+    error::Error,
+    fs,
+    io::{stdout, Write},
+    path::PathBuf,
+};
 
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 
 mod models;
 mod services;
-use models::session::Session;
+use models::{
+    config::Config,
+    keymap::Keymap,
+    room_log::{self, RoomLog},
+    session::Session,
+};
 mod views;
 use services::server_commands::ServerCommand;
 use tokio::sync::broadcast;
-use views::renderer::start_renderer;
+use views::{renderer::start_renderer, theme::Theme};
 
-use crate::services::server::Server;
+use crate::services::{
+    export::{transcript, ExportFormat},
+    server::Server,
+};
+
+const EXPORT_USAGE: &str =
+    "Usage: endl-rc export <log file or room link> [output] [--format <txt|md|json|html>]";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // setup terminal, switching to an alternate screen and disabling mouse input
+    // transcripts of the kept logs are written without opening the app
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "export") {
+        if let Err(e) = export_log(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // read the config before touching the terminal so errors are printed normally
+    let (config, keymap, theme) = match load_settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // setup terminal, switching to an alternate screen and disabling mouse input.
+    // focus changes are reported to know when mentions should notify,
+    // pastes are bracketed so their new lines do not send the message
     enable_raw_mode()?;
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableFocusChange,
+        EnableBracketedPaste
+    )?;
+    // terminals only tell Shift+Enter apart from Enter once asked to
+    let enhanced_keyboard = supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced_keyboard {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     let (server_commands_tx, server_commands_rx) = broadcast::channel::<ServerCommand>(1);
+    let (network_config, profile) = (config.network.clone(), config.profile.clone());
     let server_task = tokio::spawn(async move {
-        let mut server = Server::new();
+        let mut server = Server::new(network_config, profile);
         server.start(server_commands_rx).await
     });
     let renderer_task = tokio::spawn(async move {
-        let renderer_result =
-            start_renderer(&mut terminal, &mut Session::new(server_commands_tx)).await;
+        let renderer_result = start_renderer(
+            &mut terminal,
+            &mut Session::new(server_commands_tx, config, keymap, theme),
+        )
+        .await;
+        if enhanced_keyboard {
+            let _ = execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags);
+        }
         let _ = disable_raw_mode();
         execute!(
             terminal.backend_mut(),
             LeaveAlternateScreen,
-            DisableMouseCapture
+            DisableMouseCapture,
+            DisableFocusChange,
+            DisableBracketedPaste
         )
         .unwrap();
         let _ = terminal.show_cursor();
@@ -52,3 +119,48 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
     Ok(())
 }
+
+/// Writes the transcript of a room log to a file, or to stdout when no output is given
+fn export_log(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut format = None;
+    let mut positionals = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Some(args.next().ok_or(EXPORT_USAGE)?.as_str()),
+            "-h" | "--help" => return Err(EXPORT_USAGE.into()),
+            _ => positionals.push(arg),
+        }
+    }
+    let (log, output) = match positionals[..] {
+        [log] => (log, None),
+        [log, output] => (log, Some(PathBuf::from(output))),
+        _ => return Err(EXPORT_USAGE.into()),
+    };
+    // the log of a room is found from its link too
+    let path = match PathBuf::from(log) {
+        path if path.exists() => path,
+        _ => RoomLog::of_room(log).path().to_owned(),
+    };
+    let messages =
+        room_log::read(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let format = ExportFormat::resolve(format, output.as_deref())?;
+    let title = path
+        .file_stem()
+        .map_or_else(|| log.clone(), |stem| stem.to_string_lossy().into_owned());
+    let transcript = transcript(&messages, format, &format!("endl-rc · {}", title));
+    match output {
+        Some(output) => fs::write(&output, transcript)
+            .map_err(|e| format!("could not write {}: {}", output.display(), e))?,
+        None => stdout().write_all(transcript.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// The config file along with the key bindings it describes
+fn load_settings() -> Result<(Config, Keymap, Theme), Box<dyn Error>> {
+    let config = Config::load()?;
+    let keymap = Keymap::new(&config.keys)?;
+    let theme = Theme::load(&config.display.theme)?;
+    Ok((config, keymap, theme))
+}
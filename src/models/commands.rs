@@ -1,7 +1,486 @@
-pub enum Command {
-    Unknown,
-    Invite,
-    Join(String),
-    Quit,
-    Run,
+use super::emoji::parse_reaction;
+use super::modes::InputMode;
+use super::session::Session;
+use crate::services::export::ExportFormat;
+use crate::views::theme::Theme;
+
+/// What a command does once parsed, Err(()) quits the app
+pub type Handler = fn(&mut Session, Vec<String>) -> Result<InputMode, ()>;
+
+/// The kind of value an argument takes, used to validate and complete it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// a link to a room, completed from the recent connections
+    JoinTarget,
+    /// an emoji or a ":shortcode:"
+    Reaction,
+    /// the name of a theme, completed from the available ones
+    Theme,
+    /// a file, completed from the file system
+    Path,
+    /// a user of the room
+    User,
+    /// a file offered to the local user, completed from the pending offers
+    Offer,
+    /// a format the messages are exported in
+    ExportFormat,
+    /// any text
+    Text,
+    /// an option written alone, e.g. "--announce", passed as "on" when given
+    Flag,
+}
+
+/// An argument of a command, those named "--<name>" are options written along with their value,
+/// e.g. "--format md", or alone for flags, anywhere after the command.
+/// The last argument that is not an option takes the rest of the line, up to the next option,
+/// when it is a text, a path or an offered file, so that it may contain spaces.
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+/// A command of the Command mode
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [ArgSpec],
+    pub description: &'static str,
+    pub handler: Handler,
+}
+
+/// Every command of the Command mode, in the order they are listed in the help
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "join",
+        aliases: &["j"],
+        args: &[ArgSpec {
+            name: "link",
+            kind: ArgKind::JoinTarget,
+            optional: false,
+        }],
+        description: "join a room",
+        handler: |session, args| Ok(InputMode::Info(session.join_room(&args[0]))),
+    },
+    CommandSpec {
+        name: "connections",
+        aliases: &["servers"],
+        args: &[],
+        description: "list the saved and recent rooms",
+        handler: |_, _| Ok(InputMode::Connections),
+    },
+    CommandSpec {
+        name: "save",
+        aliases: &[],
+        args: &[
+            ArgSpec {
+                name: "link",
+                kind: ArgKind::JoinTarget,
+                optional: false,
+            },
+            ArgSpec {
+                name: "nickname",
+                kind: ArgKind::Text,
+                optional: true,
+            },
+            ArgSpec {
+                name: "--fingerprint",
+                kind: ArgKind::Text,
+                optional: true,
+            },
+        ],
+        description: "save a room to the connections",
+        handler: |session, args| Ok(session.save_endpoint(&args)),
+    },
+    CommandSpec {
+        name: "discover",
+        aliases: &[],
+        args: &[],
+        description: "find the rooms of the local network",
+        handler: |session, _| Ok(session.discover_rooms()),
+    },
+    CommandSpec {
+        name: "run",
+        aliases: &["host"],
+        args: &[ArgSpec {
+            name: "--announce",
+            kind: ArgKind::Flag,
+            optional: true,
+        }],
+        description: "start hosting a room, announced on the local network with --announce",
+        handler: |session, args| {
+            let announce = args.first().is_some_and(|flag| !flag.is_empty());
+            Ok(InputMode::Info(session.host_room(announce)))
+        },
+    },
+    CommandSpec {
+        name: "inv",
+        aliases: &["invite"],
+        args: &[],
+        description: "copy session link to clipboard",
+        // TODO: figure out how to pass link from server
+        // cli_clipboard::set_contents(self.server.get_invite_link()).unwrap();
+        handler: |_, _| Ok(InputMode::Info(String::from("Not yet implemented!"))),
+    },
+    CommandSpec {
+        name: "send",
+        aliases: &[],
+        args: &[
+            ArgSpec {
+                name: "path",
+                kind: ArgKind::Path,
+                optional: false,
+            },
+            ArgSpec {
+                name: "--to",
+                kind: ArgKind::User,
+                optional: true,
+            },
+        ],
+        description: "offer a file to the room, or to a user",
+        handler: |session, args| Ok(session.send_file(&args[0], args.get(1).map(String::as_str))),
+    },
+    CommandSpec {
+        name: "accept",
+        aliases: &[],
+        args: &[ArgSpec {
+            name: "file",
+            kind: ArgKind::Offer,
+            optional: true,
+        }],
+        description: "receive a file offered to you",
+        handler: |session, args| Ok(session.answer_offer(args.first().map(String::as_str), true)),
+    },
+    CommandSpec {
+        name: "reject",
+        aliases: &[],
+        args: &[ArgSpec {
+            name: "file",
+            kind: ArgKind::Offer,
+            optional: true,
+        }],
+        description: "decline a file offered to you",
+        handler: |session, args| Ok(session.answer_offer(args.first().map(String::as_str), false)),
+    },
+    CommandSpec {
+        name: "react",
+        aliases: &[],
+        args: &[ArgSpec {
+            name: "emoji|:shortcode:",
+            kind: ArgKind::Reaction,
+            optional: false,
+        }],
+        description: "react to the selected message",
+        handler: |session, args| {
+            if session.selected.is_none() {
+                return Ok(InputMode::Info(String::from(
+                    "Select a message to react to first",
+                )));
+            }
+            session.react_to_selected_msg(&args[0]);
+            Ok(InputMode::Normal)
+        },
+    },
+    CommandSpec {
+        name: "theme",
+        aliases: &[],
+        args: &[ArgSpec {
+            name: "name",
+            kind: ArgKind::Theme,
+            optional: true,
+        }],
+        description: "switch to a theme, or list them",
+        handler: |session, args| {
+            let Some(name) = args.first() else {
+                return Ok(InputMode::Info(format!(
+                    "Themes: {}",
+                    Theme::available().join(", ")
+                )));
+            };
+            match Theme::load(name) {
+                Ok(theme) => {
+                    session.theme = theme;
+                    Ok(InputMode::Normal)
+                }
+                Err(e) => Ok(InputMode::Info(e)),
+            }
+        },
+    },
+    CommandSpec {
+        name: "search",
+        aliases: &["s"],
+        args: &[ArgSpec {
+            name: "query",
+            kind: ArgKind::Text,
+            optional: true,
+        }],
+        description: "search the messages, starting with a word or a filter such as from:alice",
+        handler: |session, args| Ok(session.open_search(args.first().map(String::as_str))),
+    },
+    CommandSpec {
+        name: "export",
+        aliases: &[],
+        args: &[
+            ArgSpec {
+                name: "path",
+                kind: ArgKind::Path,
+                optional: true,
+            },
+            ArgSpec {
+                name: "--format",
+                kind: ArgKind::ExportFormat,
+                optional: true,
+            },
+        ],
+        description: "write the messages of the room to a file, in txt, md, json or html",
+        handler: |session, args| {
+            let arg = |i: usize| {
+                args.get(i)
+                    .map(String::as_str)
+                    .filter(|arg| !arg.is_empty())
+            };
+            Ok(InputMode::Info(session.export(arg(0), arg(1))))
+        },
+    },
+    CommandSpec {
+        name: "raw",
+        aliases: &[],
+        args: &[ArgSpec {
+            name: "user",
+            kind: ArgKind::User,
+            optional: true,
+        }],
+        description: "show the messages, or those of a user, as raw text or formatted",
+        handler: |session, args| {
+            Ok(InputMode::Info(
+                session.toggle_raw(args.first().map(String::as_str)),
+            ))
+        },
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h"],
+        args: &[],
+        description: "show the help message",
+        handler: |_, _| Ok(InputMode::Help),
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        args: &[],
+        description: "quit",
+        handler: |_, _| Err(()),
+    },
+];
+
+impl CommandSpec {
+    /// e.g. "join <link>", optional arguments are bracketed
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        for arg in self.args {
+            if arg.kind == ArgKind::Flag {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else if let Some(option) = arg.name.strip_prefix("--") {
+                usage.push_str(&format!(" [{} <{}>]", arg.name, option));
+            } else if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+    fn matches(&self, word: &str) -> bool {
+        self.name == word || self.aliases.contains(&word)
+    }
+    /// The argument the word following the given ones is the value of, the command excluded
+    pub fn arg_after(&self, words: &[&str]) -> Option<&ArgSpec> {
+        let option = |word: &str| self.args.iter().find(|arg| arg.name == word);
+        if let Some(option) = words.last().and_then(|word| option(word)) {
+            if option.kind != ArgKind::Flag {
+                return Some(option);
+            }
+        }
+        // options other than flags take the word following them
+        let mut positionals = 0;
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            if word.starts_with("--") {
+                if option(word).is_some_and(|option| option.kind != ArgKind::Flag) {
+                    words.next();
+                }
+            } else {
+                positionals += 1;
+            }
+        }
+        self.args
+            .iter()
+            .filter(|arg| !arg.name.starts_with("--"))
+            .nth(positionals)
+    }
+}
+
+impl ArgKind {
+    /// Whether the value may contain spaces, when it is the last one of the command
+    fn takes_spaces(self) -> bool {
+        matches!(self, ArgKind::Text | ArgKind::Path | ArgKind::Offer)
+    }
+    /// Checks a value, returns it in the form expected by the handler
+    fn validate(self, value: &str) -> Option<String> {
+        match self {
+            ArgKind::Reaction => parse_reaction(value),
+            // TODO: verify the link
+            ArgKind::JoinTarget
+            | ArgKind::Theme
+            | ArgKind::Path
+            | ArgKind::User
+            | ArgKind::Offer
+            | ArgKind::Text
+            | ArgKind::Flag => Some(value.to_owned()),
+            ArgKind::ExportFormat => value
+                .parse::<ExportFormat>()
+                .ok()
+                .map(|_| value.to_ascii_lowercase()),
+        }
+    }
+}
+
+/// Looks a command up by its name or one of its aliases
+pub fn find_command(word: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|command| command.matches(word))
+}
+
+/// Parses a command line into its command and validated arguments, in the order of the spec,
+/// or the message explaining why it is not valid.
+/// An optional argument left out before a given one is passed as an empty string.
+pub fn parse_command(input: &str) -> Result<(&'static CommandSpec, Vec<String>), String> {
+    let mut words = input.split_whitespace().peekable();
+    let Some(word) = words.next() else {
+        return Err(String::from("Enter a command, see \"help\""));
+    };
+    let command =
+        find_command(word).ok_or_else(|| format!("Unknown command \"{}\", see \"help\"", word))?;
+    let usage = || format!("Usage: {}", command.usage());
+    let mut values: Vec<Option<&str>> = vec![None; command.args.len()];
+    let mut positionals = command
+        .args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| !arg.name.starts_with("--"))
+        .map(|(i, _)| i)
+        .peekable();
+    let is_option = |word: &str| command.args.iter().any(|arg| arg.name == word);
+    while let Some(word) = words.next() {
+        let i = if word.starts_with("--") {
+            command
+                .args
+                .iter()
+                .position(|arg| arg.name == word)
+                .ok_or_else(usage)?
+        } else {
+            positionals.next().ok_or_else(usage)?
+        };
+        let value = if command.args[i].kind == ArgKind::Flag {
+            "on"
+        } else if word.starts_with("--") {
+            words.next().ok_or_else(usage)?
+        } else if positionals.peek().is_none() && command.args[i].kind.takes_spaces() {
+            // the value ends with the last word before the next option, spaces included
+            let start = offset(input, word);
+            let mut end = start + word.len();
+            while let Some(next) = words.next_if(|next| !is_option(next)) {
+                end = offset(input, next) + next.len();
+            }
+            &input[start..end]
+        } else {
+            word
+        };
+        values[i] = Some(value);
+    }
+    let given = values
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |i| i + 1);
+    command
+        .args
+        .iter()
+        .zip(values)
+        .take(given)
+        .map(|(arg, value)| match value {
+            Some(value) => arg.kind.validate(value).ok_or_else(usage),
+            None if arg.optional => Ok(String::new()),
+            None => Err(usage()),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|args| {
+            // the required arguments after the last one given are missing too
+            let missing = command.args[args.len()..].iter().any(|arg| !arg.optional);
+            if missing {
+                Err(usage())
+            } else {
+                Ok((command, args))
+            }
+        })
+}
+
+/// The position of a word taken from the input
+fn offset(input: &str, word: &str) -> usize {
+    word.as_ptr() as usize - input.as_ptr() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &str) -> Vec<String> {
+        parse_command(input).unwrap().1
+    }
+
+    #[test]
+    fn gives_the_rest_of_the_line_to_the_last_text() {
+        assert_eq!(
+            args("search from:alice  deploy fix"),
+            ["from:alice  deploy fix"]
+        );
+        assert_eq!(
+            args("save 10.0.0.5:8080 lab pi"),
+            ["10.0.0.5:8080", "lab pi"]
+        );
+        assert_eq!(
+            args("save 10.0.0.5:8080 lab pi --fingerprint ab:cd"),
+            ["10.0.0.5:8080", "lab pi", "ab:cd"]
+        );
+    }
+
+    #[test]
+    fn ends_paths_at_the_next_option() {
+        assert_eq!(args("export ~/my notes.md"), ["~/my notes.md"]);
+        assert_eq!(args("export a b.md --format txt"), ["a b.md", "txt"]);
+        assert_eq!(args("export --format txt a b.md"), ["a b.md", "txt"]);
+        assert_eq!(args("send ~/my notes.txt"), ["~/my notes.txt"]);
+        assert_eq!(
+            args("send ~/my notes.txt --to bob"),
+            ["~/my notes.txt", "bob"]
+        );
+        assert_eq!(
+            args("send --to bob ~/my notes.txt"),
+            ["~/my notes.txt", "bob"]
+        );
+        assert_eq!(args("accept my notes.txt"), ["my notes.txt"]);
+    }
+
+    #[test]
+    fn passes_flags_without_a_value() {
+        assert_eq!(args("run --announce"), ["on"]);
+        assert!(args("run").is_empty());
+    }
+
+    #[test]
+    fn rejects_missing_and_extra_arguments() {
+        assert!(parse_command("save").is_err());
+        assert!(parse_command("run announce").is_err());
+        assert!(parse_command("send a.txt --to").is_err());
+        assert!(parse_command("join a b").is_err());
+        assert!(parse_command("unknown").is_err());
+    }
 }
@@ -0,0 +1,39 @@
+/// Suggestions for the word before the cursor, cycled through with Tab
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub candidates: Vec<String>,
+    /// index of the candidate currently inserted in the input
+    pub selected: usize,
+}
+
+impl Completion {
+    /// Candidates starting with the prefix, ignoring ASCII case, without duplicates.
+    /// Returns None when nothing matches.
+    pub fn new<'a>(prefix: &str, sources: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let prefix = prefix.to_ascii_lowercase();
+        let mut candidates: Vec<String> = vec![];
+        for candidate in sources {
+            if candidate.to_ascii_lowercase().starts_with(&prefix)
+                && !candidates.iter().any(|c| c == candidate)
+            {
+                candidates.push(candidate.to_owned());
+            }
+        }
+        (!candidates.is_empty()).then_some(Self {
+            candidates,
+            selected: 0,
+        })
+    }
+    pub fn current(&self) -> &str {
+        &self.candidates[self.selected]
+    }
+    /// Moves to the next candidate, or the previous one, wrapping around
+    pub fn cycle(&mut self, forward: bool) {
+        let len = self.candidates.len();
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+}
@@ -0,0 +1,314 @@
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer};
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf, str::FromStr, time::Duration};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// User configuration, read from `<config dir>/endl-rc/config.toml`.
+/// Every field is optional, missing ones fall back to their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub profile: ProfileConfig,
+    pub display: DisplayConfig,
+    pub notifications: NotificationsConfig,
+    pub network: NetworkConfig,
+    pub transfers: TransfersConfig,
+    pub links: LinksConfig,
+    pub history: HistoryConfig,
+    pub keys: KeysConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// name shown to the other users of the room
+    pub name: String,
+    /// color of your name, e.g. "lightgreen" or "#ff8800", derived from the name when unset
+    #[serde(deserialize_with = "deserialize_color")]
+    pub color: Option<Color>,
+    /// let the room know when you are typing
+    pub typing_indicators: bool,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            name: std::env::var("USER").unwrap_or_else(|_| String::from("anonymous")),
+            color: None,
+            typing_indicators: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub timestamps: TimestampFormat,
+    /// a built-in theme or the name of a file of `<config dir>/endl-rc/themes`
+    pub theme: String,
+    /// render *italic*, **bold**, `code` and the like, otherwise messages are shown as raw text
+    pub markdown: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            timestamps: TimestampFormat::default(),
+            theme: String::from("tron"),
+            markdown: true,
+        }
+    }
+}
+
+/// How message timestamps are shown in the message pane
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    /// 24 hours clock, e.g. 14:05
+    #[default]
+    #[serde(rename = "hh:mm")]
+    Clock,
+    /// time elapsed since the message was sent, e.g. 5m
+    Relative,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// words highlighted and notified like a mention of your name
+    pub highlight_words: Vec<String>,
+    /// ring the terminal bell on mentions
+    pub bell: bool,
+    pub desktop: DesktopNotification,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            highlight_words: vec![],
+            bell: true,
+            desktop: DesktopNotification::default(),
+        }
+    }
+}
+
+/// Escape sequence used to ask the terminal for a desktop notification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DesktopNotification {
+    /// understood by iTerm2, kitty, WezTerm and others
+    #[default]
+    Osc9,
+    /// understood by rxvt-unicode and VTE based terminals
+    Osc777,
+    Off,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// seconds between two pings sent to a peer
+    pub ping_interval_secs: u64,
+    /// seconds of silence after which a peer is considered dead
+    pub peer_timeout_secs: u64,
+    /// how many times a client tries to reach a lost host before giving up
+    pub reconnect_attempts: u32,
+    /// seconds to wait between two reconnection attempts
+    pub reconnect_delay_secs: u64,
+    /// announce the rooms you host on the local network, as `run --announce` does
+    pub announce: bool,
+    /// name of the rooms you host, "<name>'s room" when unset
+    pub room_name: Option<String>,
+    /// address the rooms you host listen on, see `bind_address`
+    pub bind: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_secs: 5,
+            peer_timeout_secs: 15,
+            reconnect_attempts: 5,
+            reconnect_delay_secs: 2,
+            announce: false,
+            room_name: None,
+            bind: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.ping_interval_secs.max(1))
+    }
+    pub fn peer_timeout(&self) -> Duration {
+        Duration::from_secs(self.peer_timeout_secs.max(self.ping_interval_secs + 1))
+    }
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_delay_secs)
+    }
+    /// The address set, or port 8080 of every interface when the rooms are announced,
+    /// since they are joined from other machines, and of the loopback interface otherwise
+    pub fn bind_address(&self, announced: bool) -> String {
+        match &self.bind {
+            Some(bind) => bind.clone(),
+            None if announced => String::from("0.0.0.0:8080"),
+            None => String::from("127.0.0.1:8080"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TransfersConfig {
+    /// where the accepted files are saved, `<downloads dir>/endl-rc` when unset
+    pub downloads_dir: Option<PathBuf>,
+    /// largest file sent or accepted, in megabytes
+    pub max_size_mb: u64,
+}
+
+impl Default for TransfersConfig {
+    fn default() -> Self {
+        Self {
+            downloads_dir: None,
+            max_size_mb: 50,
+        }
+    }
+}
+
+impl TransfersConfig {
+    pub fn downloads_dir(&self) -> PathBuf {
+        match &self.downloads_dir {
+            Some(dir) => match dir.strip_prefix("~").ok().zip(dirs::home_dir()) {
+                Some((rest, home)) => home.join(rest),
+                None => dir.clone(),
+            },
+            None => dirs::download_dir()
+                .map(|dir| dir.join("endl-rc"))
+                .unwrap_or_else(|| data_dir().join("downloads")),
+        }
+    }
+    /// in bytes
+    pub fn max_size(&self) -> u64 {
+        self.max_size_mb * 1024 * 1024
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LinksConfig {
+    /// command the links are opened with, the link being its last argument, e.g. "firefox --new-tab"
+    pub opener: Option<String>,
+    /// fetch the previews of the links posted in the rooms you host
+    pub previews: bool,
+    /// HTTP proxy on this machine the previews are fetched through
+    pub proxy: String,
+    /// domains whose links are previewed, along with their subdomains
+    pub preview_domains: Vec<String>,
+}
+
+impl Default for LinksConfig {
+    fn default() -> Self {
+        Self {
+            opener: None,
+            previews: false,
+            proxy: String::from("127.0.0.1:8118"),
+            preview_domains: vec![],
+        }
+    }
+}
+
+impl LinksConfig {
+    /// The program and the arguments opening a link, the link left out
+    pub fn opener(&self) -> Vec<String> {
+        match &self.opener {
+            Some(opener) => opener.split_whitespace().map(String::from).collect(),
+            None if cfg!(target_os = "macos") => vec![String::from("open")],
+            None if cfg!(windows) => ["cmd", "/C", "start", ""].map(String::from).to_vec(),
+            None => vec![String::from("xdg-open")],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// keep the messages of each room in `<data dir>/endl-rc/logs`, where they can be searched
+    pub log_messages: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { log_messages: true }
+    }
+}
+
+/// The bindings of an input context, as written in the config file: key chord -> action name
+pub type Bindings = BTreeMap<String, String>;
+
+/// Key bindings, `[keys]` in the config file.
+/// The preset is applied on top of the default bindings, then the tables of each mode.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    pub preset: KeyPreset,
+    pub normal: Bindings,
+    pub typing: Bindings,
+    pub command: Bindings,
+    /// the help and info popups
+    pub popup: Bindings,
+    /// the connection manager
+    pub connections: Bindings,
+    /// the link picker
+    pub links: Bindings,
+    /// the message search
+    pub search: Bindings,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyPreset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl Config {
+    /// Loads the config file, a missing file yields the default config
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let path = config_dir().join(CONFIG_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("invalid config file {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Directory holding the app's configuration files
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("endl-rc")
+}
+
+/// Directory holding the app's state, such as the input history
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("endl-rc")
+}
+
+/// Parses colors written as names ("lightblue"), indexes ("42") or hex codes ("#ff8800")
+fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    Color::from_str(&color)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid color \"{}\"", color)))
+}
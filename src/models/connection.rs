@@ -0,0 +1,24 @@
+/// State of the link to the room, as reported by the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// no room was joined or hosted yet
+    #[default]
+    Offline,
+    Connecting,
+    Connected,
+    /// the host went away, attempt number is attached
+    Reconnecting(u32),
+    Disconnected,
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offline => write!(f, "offline"),
+            Self::Connecting => write!(f, "connecting"),
+            Self::Connected => write!(f, "connected"),
+            Self::Reconnecting(attempt) => write!(f, "reconnecting ({})", attempt),
+            Self::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
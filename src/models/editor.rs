@@ -0,0 +1,359 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// killed texts remembered for yanking, the oldest ones are dropped first
+const KILL_RING_CAPACITY: usize = 16;
+
+/// A multiline text editor backing the input box.
+/// The cursor is a line index along with a char index within that line.
+/// Text removed by the emacs kill commands is kept in a ring, to be yanked back later.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    kill_ring: Vec<String>,
+    // whether the last key killed text, consecutive kills are merged
+    killing: bool,
+    // state before the last yank and the index of the yanked kill, to cycle with Alt-Y
+    yanked: Option<(Vec<String>, usize, usize, usize)>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            kill_ring: vec![],
+            killing: false,
+            yanked: None,
+        }
+    }
+}
+
+impl Editor {
+    pub fn value(&self) -> String {
+        self.lines.join("\n")
+    }
+    /// Replaces the text, with the cursor at its end. The kill ring is kept.
+    pub fn set_value(&mut self, value: &str) {
+        self.reset();
+        self.insert_str(value);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lines.len() == 1 && self.lines[0].is_empty()
+    }
+    pub fn reset(&mut self) {
+        self.lines = vec![String::new()];
+        self.row = 0;
+        self.col = 0;
+        self.yanked = None;
+    }
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+    /// The line of the cursor, and the text preceding it on that line
+    pub fn cursor(&self) -> (usize, &str) {
+        let line = &self.lines[self.row];
+        (self.row, &line[..byte_index(line, self.col)])
+    }
+    /// The word the cursor is at the end of, empty after a space
+    pub fn word_before_cursor(&self) -> &str {
+        let (_, before) = self.cursor();
+        before
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+    }
+    /// Replaces the word the cursor is at the end of
+    pub fn replace_word_before_cursor(&mut self, with: &str) {
+        let start = self.col - self.word_before_cursor().chars().count();
+        self.remove_range(start, self.col);
+        self.insert_str(with);
+    }
+    /// Applies a key press, returns whether the text changed.
+    /// Shift+Enter and Alt+Enter insert a new line, a plain Enter is left to the caller.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        let before = self.lines.clone();
+        let killing = std::mem::take(&mut self.killing);
+        let yanked = self.yanked.take();
+        match key.code {
+            KeyCode::Enter if alt || key.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.insert_newline()
+            }
+            KeyCode::Char('a') if ctrl => self.col = 0,
+            KeyCode::Char('e') if ctrl => self.col = self.line_len(),
+            KeyCode::Char('b') if ctrl => self.move_left(),
+            KeyCode::Char('f') if ctrl => self.move_right(),
+            KeyCode::Char('b') if alt => self.move_word_left(),
+            KeyCode::Char('f') if alt => self.move_word_right(),
+            KeyCode::Char('h') if ctrl => self.delete_prev_char(),
+            KeyCode::Char('d') if ctrl => self.delete_next_char(),
+            KeyCode::Char('w') if ctrl => {
+                let killed = self.delete_prev_word();
+                self.kill(killed, killing, true);
+            }
+            KeyCode::Char('d') if alt => {
+                let killed = self.delete_next_word();
+                self.kill(killed, killing, false);
+            }
+            KeyCode::Char('u') if ctrl => {
+                let killed = self.delete_to_line_start();
+                self.kill(killed, killing, true);
+            }
+            KeyCode::Char('k') if ctrl => {
+                let killed = self.delete_to_line_end();
+                self.kill(killed, killing, false);
+            }
+            KeyCode::Char('y') if ctrl => self.yank(self.kill_ring.len()),
+            KeyCode::Char('y') if alt => {
+                // only cycles right after a yank
+                if let Some((lines, row, col, index)) = yanked {
+                    (self.lines, self.row, self.col) = (lines, row, col);
+                    self.yank(if index == 0 {
+                        self.kill_ring.len()
+                    } else {
+                        index
+                    });
+                }
+            }
+            KeyCode::Char('t') if ctrl => self.transpose_chars(),
+            KeyCode::Char(_) if ctrl || alt => {}
+            KeyCode::Char(c) => self.insert_char(c),
+            KeyCode::Backspace if alt || ctrl => {
+                let killed = self.delete_prev_word();
+                self.kill(killed, killing, true);
+            }
+            KeyCode::Backspace => self.delete_prev_char(),
+            KeyCode::Delete => self.delete_next_char(),
+            KeyCode::Left if alt || ctrl => self.move_word_left(),
+            KeyCode::Right if alt || ctrl => self.move_word_right(),
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Up => {
+                self.move_up();
+            }
+            KeyCode::Down => {
+                self.move_down();
+            }
+            KeyCode::Home => self.col = 0,
+            KeyCode::End => self.col = self.line_len(),
+            _ => {}
+        }
+        before != self.lines
+    }
+    /// Inserts text at the cursor, new lines included
+    pub fn insert_str(&mut self, text: &str) {
+        // terminals may paste line breaks as carriage returns
+        for c in text.replace("\r\n", "\n").replace('\r', "\n").chars() {
+            match c {
+                '\n' => self.insert_newline(),
+                c => self.insert_char(c),
+            }
+        }
+    }
+    pub fn insert_char(&mut self, c: char) {
+        let line = &mut self.lines[self.row];
+        line.insert(byte_index(line, self.col), c);
+        self.col += 1;
+    }
+    pub fn insert_newline(&mut self) {
+        let line = &mut self.lines[self.row];
+        let rest = line.split_off(byte_index(line, self.col));
+        self.row += 1;
+        self.col = 0;
+        self.lines.insert(self.row, rest);
+    }
+    /// Moves the cursor one line up, returns false when already on the first line
+    pub fn move_up(&mut self) -> bool {
+        if self.row == 0 {
+            return false;
+        }
+        self.row -= 1;
+        self.col = self.col.min(self.line_len());
+        true
+    }
+    /// Moves the cursor one line down, returns false when already on the last line
+    pub fn move_down(&mut self) -> bool {
+        if self.row + 1 == self.lines.len() {
+            return false;
+        }
+        self.row += 1;
+        self.col = self.col.min(self.line_len());
+        true
+    }
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+    fn move_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len();
+        }
+    }
+    fn move_right(&mut self) {
+        if self.col < self.line_len() {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+    /// Start of the word before the cursor, on the cursor's line
+    fn prev_word_start(&self) -> usize {
+        let chars = self.lines[self.row].chars().collect::<Vec<_>>();
+        let mut col = self.col;
+        while col > 0 && chars[col - 1].is_whitespace() {
+            col -= 1;
+        }
+        while col > 0 && !chars[col - 1].is_whitespace() {
+            col -= 1;
+        }
+        col
+    }
+    /// End of the word after the cursor, on the cursor's line
+    fn next_word_end(&self) -> usize {
+        let chars = self.lines[self.row].chars().collect::<Vec<_>>();
+        let mut col = self.col;
+        while col < chars.len() && chars[col].is_whitespace() {
+            col += 1;
+        }
+        while col < chars.len() && !chars[col].is_whitespace() {
+            col += 1;
+        }
+        col
+    }
+    fn move_word_left(&mut self) {
+        if self.col == 0 {
+            self.move_left();
+        } else {
+            self.col = self.prev_word_start();
+        }
+    }
+    fn move_word_right(&mut self) {
+        if self.col == self.line_len() {
+            self.move_right();
+        } else {
+            self.col = self.next_word_end();
+        }
+    }
+    /// Removes the chars between two columns of the cursor's line, returns them
+    fn remove_range(&mut self, from: usize, to: usize) -> String {
+        let line = &mut self.lines[self.row];
+        let range = byte_index(line, from)..byte_index(line, to);
+        self.col = from;
+        line.drain(range).collect()
+    }
+    /// Joins the cursor's line to the previous one, returns the removed line break
+    fn join_prev_line(&mut self) -> String {
+        if self.row == 0 {
+            return String::new();
+        }
+        let line = self.lines.remove(self.row);
+        self.row -= 1;
+        self.col = self.line_len();
+        self.lines[self.row].push_str(&line);
+        String::from("\n")
+    }
+    /// Joins the next line to the cursor's one, returns the removed line break
+    fn join_next_line(&mut self) -> String {
+        if self.row + 1 == self.lines.len() {
+            return String::new();
+        }
+        let (row, col) = (self.row, self.col);
+        self.row += 1;
+        self.join_prev_line();
+        (self.row, self.col) = (row, col);
+        String::from("\n")
+    }
+    fn delete_prev_char(&mut self) {
+        if self.col == 0 {
+            self.join_prev_line();
+        } else {
+            self.remove_range(self.col - 1, self.col);
+        }
+    }
+    fn delete_next_char(&mut self) {
+        if self.col < self.line_len() {
+            self.remove_range(self.col, self.col + 1);
+        } else {
+            self.join_next_line();
+        }
+    }
+    fn delete_prev_word(&mut self) -> String {
+        if self.col == 0 {
+            self.join_prev_line()
+        } else {
+            self.remove_range(self.prev_word_start(), self.col)
+        }
+    }
+    fn delete_next_word(&mut self) -> String {
+        if self.col == self.line_len() {
+            return self.join_next_line();
+        }
+        let end = self.next_word_end();
+        self.remove_range(self.col, end)
+    }
+    fn delete_to_line_start(&mut self) -> String {
+        self.remove_range(0, self.col)
+    }
+    /// Like emacs, the line break is deleted when the cursor is at the end of the line
+    fn delete_to_line_end(&mut self) -> String {
+        if self.col == self.line_len() {
+            return self.join_next_line();
+        }
+        let end = self.line_len();
+        self.remove_range(self.col, end)
+    }
+    /// Saves killed text to the ring, merged with the previous kill when they follow each other
+    fn kill(&mut self, text: String, merge: bool, backward: bool) {
+        self.killing = true;
+        if text.is_empty() {
+            return;
+        }
+        match self.kill_ring.last_mut() {
+            Some(last) if merge && backward => last.insert_str(0, &text),
+            Some(last) if merge => last.push_str(&text),
+            _ => {
+                self.kill_ring.push(text);
+                if self.kill_ring.len() > KILL_RING_CAPACITY {
+                    self.kill_ring.remove(0);
+                }
+            }
+        }
+    }
+    /// Inserts the kill preceding the given ring index, the newest when given the ring's length
+    fn yank(&mut self, index: usize) {
+        let Some(index) = index.checked_sub(1) else {
+            return;
+        };
+        self.yanked = Some((self.lines.clone(), self.row, self.col, index));
+        let text = self.kill_ring[index].clone();
+        self.insert_str(&text);
+    }
+    /// Swaps the chars around the cursor, or the last two of the line at its end
+    fn transpose_chars(&mut self) {
+        let len = self.line_len();
+        if len < 2 || self.col == 0 {
+            return;
+        }
+        let col = self.col.min(len - 1);
+        let swapped = self.remove_range(col - 1, col);
+        self.col = col;
+        self.insert_char(swapped.chars().next().unwrap_or_default());
+        // the cursor stays after the swapped chars
+        self.col = (col + 1).min(len);
+    }
+}
+
+/// Byte offset of a char index, the end of the string when out of bounds
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices()
+        .nth(col)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len())
+}
@@ -0,0 +1,46 @@
+/// Reactions sent with the number keys in Normal mode, <1> being the first one
+pub const QUICK_REACTIONS: [&str; 6] = ["👍", "👎", "😂", "🎉", "👀", "🔥"];
+/// longest reaction accepted, in characters
+pub const MAX_REACTION_LEN: usize = 8;
+
+const SHORTCODES: [(&str, &str); 20] = [
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("joy", "😂"),
+    ("smile", "😄"),
+    ("heart", "❤️"),
+    ("tada", "🎉"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("thinking", "🤔"),
+    ("pray", "🙏"),
+    ("wave", "👋"),
+    ("100", "💯"),
+    ("clap", "👏"),
+    ("check", "✅"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("sob", "😭"),
+];
+
+/// The known shortcodes, e.g. ":+1:"
+pub fn shortcodes() -> impl Iterator<Item = String> {
+    SHORTCODES.iter().map(|(code, _)| format!(":{}:", code))
+}
+
+/// Turns ":+1:" into "👍", anything else is kept as long as it is short enough to be a reaction
+pub fn parse_reaction(input: &str) -> Option<String> {
+    let input = input.trim();
+    if let Some(code) = input.strip_prefix(':').and_then(|s| s.strip_suffix(':')) {
+        return SHORTCODES
+            .iter()
+            .find(|(shortcode, _)| *shortcode == code)
+            .map(|(_, emoji)| emoji.to_string());
+    }
+    let len = input.chars().count();
+    (len > 0 && len <= MAX_REACTION_LEN && !input.chars().any(char::is_whitespace))
+        .then(|| input.to_owned())
+}
@@ -0,0 +1,163 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, path::PathBuf};
+
+use super::config::data_dir;
+
+const ENDPOINTS_FILE: &str = "endpoints.json";
+/// rooms kept without being saved, the least recently seen ones are dropped first
+const RECENT_CAPACITY: usize = 10;
+
+/// A room that can be joined from the connection manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub link: String,
+    /// name shown instead of the link
+    pub nickname: Option<String>,
+    /// when the room was last reached, in milliseconds since the epoch
+    pub last_seen: Option<i64>,
+    /// fingerprint the host is expected to present
+    // TODO: check it once links are encrypted
+    pub fingerprint: Option<String>,
+    /// saved endpoints are kept until deleted, the others are the recent ones
+    pub saved: bool,
+}
+
+impl Endpoint {
+    pub fn name(&self) -> &str {
+        self.nickname.as_deref().unwrap_or(&self.link)
+    }
+}
+
+/// The saved and recent endpoints, persisted across restarts.
+/// Saved endpoints come first, by name, followed by the recent ones, most recent first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    /// index of the endpoint selected in the connection manager
+    #[serde(skip)]
+    pub selected: usize,
+}
+
+impl Endpoints {
+    fn path() -> PathBuf {
+        data_dir().join(ENDPOINTS_FILE)
+    }
+    /// Reads the saved endpoints, starting afresh when there are none or they are unreadable
+    pub fn load() -> Self {
+        let mut endpoints: Self = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        endpoints.sort();
+        endpoints
+    }
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+    pub fn selected(&self) -> Option<&Endpoint> {
+        self.endpoints.get(self.selected)
+    }
+    pub fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.endpoints.len().saturating_sub(1));
+    }
+    /// Saves an endpoint, or updates the one with the same link, and selects it
+    pub fn save_endpoint(
+        &mut self,
+        link: &str,
+        nickname: Option<String>,
+        fingerprint: Option<String>,
+    ) {
+        match self
+            .endpoints
+            .iter_mut()
+            .find(|endpoint| endpoint.link == link)
+        {
+            Some(endpoint) => {
+                endpoint.nickname = nickname;
+                endpoint.fingerprint = fingerprint;
+                endpoint.saved = true;
+            }
+            None => self.endpoints.push(Endpoint {
+                link: link.to_owned(),
+                nickname,
+                last_seen: None,
+                fingerprint,
+                saved: true,
+            }),
+        }
+        self.sort();
+        self.select(link);
+    }
+    /// Deletes the selected endpoint
+    pub fn remove_selected(&mut self) -> Option<Endpoint> {
+        if self.selected >= self.endpoints.len() {
+            return None;
+        }
+        let endpoint = self.endpoints.remove(self.selected);
+        self.selected = self.selected.min(self.endpoints.len().saturating_sub(1));
+        Some(endpoint)
+    }
+    /// Records that the room behind the link was reached, remembering it as a recent one if needed
+    pub fn seen(&mut self, link: &str) {
+        let now = Utc::now().timestamp_millis();
+        match self
+            .endpoints
+            .iter_mut()
+            .find(|endpoint| endpoint.link == link)
+        {
+            Some(endpoint) => endpoint.last_seen = Some(now),
+            None => self.endpoints.push(Endpoint {
+                link: link.to_owned(),
+                nickname: None,
+                last_seen: Some(now),
+                fingerprint: None,
+                saved: false,
+            }),
+        }
+        self.sort();
+        // sorting puts the oldest recent endpoints last
+        while self
+            .endpoints
+            .iter()
+            .filter(|endpoint| !endpoint.saved)
+            .count()
+            > RECENT_CAPACITY
+        {
+            self.endpoints.pop();
+        }
+        self.selected = self.selected.min(self.endpoints.len().saturating_sub(1));
+    }
+    fn select(&mut self, link: &str) {
+        if let Some(i) = self
+            .endpoints
+            .iter()
+            .position(|endpoint| endpoint.link == link)
+        {
+            self.selected = i;
+        }
+    }
+    fn sort(&mut self) {
+        self.endpoints.sort_by(|a, b| {
+            b.saved.cmp(&a.saved).then_with(|| {
+                if a.saved {
+                    a.name().to_lowercase().cmp(&b.name().to_lowercase())
+                } else {
+                    b.last_seen.cmp(&a.last_seen)
+                }
+            })
+        });
+    }
+}
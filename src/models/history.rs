@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, path::PathBuf};
+
+use super::config::data_dir;
+
+const HISTORY_FILE: &str = "history.json";
+/// entries kept per ring, the oldest ones are dropped first
+const HISTORY_CAPACITY: usize = 500;
+
+/// Entries submitted in one input mode, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    entries: Vec<String>,
+    // entry being browsed, None when back to the draft
+    #[serde(skip)]
+    position: Option<usize>,
+    // text that was in the input box before browsing started
+    #[serde(skip)]
+    draft: String,
+}
+
+impl History {
+    /// Records a submitted entry, blank ones and repetitions of the last one are ignored
+    pub fn push(&mut self, entry: String) {
+        self.position = None;
+        if entry.trim().is_empty() || self.entries.last() == Some(&entry) {
+            return;
+        }
+        self.entries.push(entry);
+        if self.entries.len() > HISTORY_CAPACITY {
+            self.entries.remove(0);
+        }
+    }
+    /// The entry before the one being browsed, the current input is kept as the draft
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_owned();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+    /// The entry after the one being browsed, the draft once past the newest one
+    pub fn next(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 == self.entries.len() {
+            self.position = None;
+            return Some(&self.draft);
+        }
+        self.position = Some(position + 1);
+        Some(&self.entries[position + 1])
+    }
+    /// Stops browsing, the next `prev` starts from the newest entry again
+    pub fn rewind(&mut self) {
+        self.position = None;
+    }
+    /// Index of the newest entry older than `before` containing the query
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+    /// Entries from the newest to the oldest
+    pub fn recent(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().rev().map(String::as_str)
+    }
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+}
+
+/// The history of the Typing and Command modes, persisted across restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputHistory {
+    pub typing: History,
+    pub command: History,
+}
+
+impl InputHistory {
+    fn path() -> PathBuf {
+        data_dir().join(HISTORY_FILE)
+    }
+    /// Reads the saved history, starting afresh when there is none or it is unreadable
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// State of a reverse incremental search (Ctrl-R) through a history
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearch {
+    pub query: String,
+    /// index of the entry matching the query, if any
+    pub found: Option<usize>,
+    /// whether the query matches no entry older than the last match
+    pub failing: bool,
+    // input to restore when the search is cancelled
+    pub original: String,
+}
@@ -0,0 +1,624 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use super::config::{KeyPreset, KeysConfig};
+use super::emoji::QUICK_REACTIONS;
+use super::modes::InputMode;
+
+/// The set of bindings in use, which depends on the input mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyContext {
+    Normal,
+    Typing,
+    Command,
+    Popup,
+    Connections,
+    Links,
+    Search,
+}
+
+impl KeyContext {
+    pub fn of(mode: &InputMode) -> Self {
+        match mode {
+            InputMode::Normal => Self::Normal,
+            InputMode::Typing => Self::Typing,
+            InputMode::Command => Self::Command,
+            InputMode::Help | InputMode::Info(_) => Self::Popup,
+            // both list rooms to join
+            InputMode::Connections | InputMode::Discovery => Self::Connections,
+            InputMode::Links => Self::Links,
+            InputMode::Search => Self::Search,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Typing => "typing",
+            Self::Command => "command",
+            Self::Popup => "popup",
+            Self::Connections => "connections",
+            Self::Links => "links",
+            Self::Search => "search",
+        }
+    }
+}
+
+/// What a key chord can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    CommandMode,
+    TypingMode,
+    NormalMode,
+    Help,
+    Quit,
+    RetryFailed,
+    SelectPrev,
+    SelectNext,
+    Unselect,
+    Reply,
+    ToggleThread,
+    /// one of the quick reactions, 0 being the first
+    QuickReaction(usize),
+    Edit,
+    Delete,
+    ScrollUp,
+    ScrollDown,
+    ScrollToBottom,
+    Send,
+    Execute,
+    NewLine,
+    HistoryPrev,
+    HistoryNext,
+    SearchHistory,
+    Complete,
+    CompletePrev,
+    Connections,
+    Connect,
+    AddEndpoint,
+    ScrollCodeLeft,
+    ScrollCodeRight,
+    CopyCode,
+    Links,
+    OpenLink,
+    CopyLink,
+    Search,
+    JumpToResult,
+}
+
+/// Every action along with its name in the config file, in the order they are listed in the help
+const ACTIONS: [(&str, Action); 41] = [
+    ("command_mode", Action::CommandMode),
+    ("typing_mode", Action::TypingMode),
+    ("normal_mode", Action::NormalMode),
+    ("send", Action::Send),
+    ("execute", Action::Execute),
+    ("new_line", Action::NewLine),
+    ("history_prev", Action::HistoryPrev),
+    ("history_next", Action::HistoryNext),
+    ("search_history", Action::SearchHistory),
+    ("complete", Action::Complete),
+    ("complete_prev", Action::CompletePrev),
+    ("select_prev", Action::SelectPrev),
+    ("select_next", Action::SelectNext),
+    ("unselect", Action::Unselect),
+    ("edit", Action::Edit),
+    ("delete", Action::Delete),
+    ("reply", Action::Reply),
+    ("thread", Action::ToggleThread),
+    ("react_1", Action::QuickReaction(0)),
+    ("react_2", Action::QuickReaction(1)),
+    ("react_3", Action::QuickReaction(2)),
+    ("react_4", Action::QuickReaction(3)),
+    ("react_5", Action::QuickReaction(4)),
+    ("react_6", Action::QuickReaction(5)),
+    ("retry", Action::RetryFailed),
+    ("scroll_up", Action::ScrollUp),
+    ("scroll_down", Action::ScrollDown),
+    ("scroll_bottom", Action::ScrollToBottom),
+    ("code_left", Action::ScrollCodeLeft),
+    ("code_right", Action::ScrollCodeRight),
+    ("copy_code", Action::CopyCode),
+    ("links", Action::Links),
+    ("open_link", Action::OpenLink),
+    ("copy_link", Action::CopyLink),
+    ("search", Action::Search),
+    ("jump_to_result", Action::JumpToResult),
+    ("connections", Action::Connections),
+    ("connect", Action::Connect),
+    ("add", Action::AddEndpoint),
+    ("help", Action::Help),
+    ("quit", Action::Quit),
+];
+
+impl Action {
+    /// The name of the action in the config file
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action)| *action == self)
+            .map_or("none", |(name, _)| name)
+    }
+    pub fn description(self) -> String {
+        String::from(match self {
+            Action::CommandMode => "enter Command mode",
+            Action::TypingMode => "enter Typing mode",
+            Action::NormalMode => "switch back to Normal mode",
+            Action::Help => "show this help message",
+            Action::Quit => "quit",
+            Action::RetryFailed => "retry failed messages",
+            Action::SelectPrev => "select the previous message",
+            Action::SelectNext => "select the next message",
+            Action::Unselect => "unselect the message",
+            Action::Reply => "reply to the selected message",
+            Action::ToggleThread => "show the thread of the selected message",
+            Action::QuickReaction(i) => return format!("react with {}", QUICK_REACTIONS[i]),
+            Action::Edit => "edit your selected message",
+            Action::Delete => "delete your selected message",
+            Action::ScrollUp => "scroll up",
+            Action::ScrollDown => "scroll down",
+            Action::ScrollToBottom => "scroll to the newest messages",
+            Action::Send => "send",
+            Action::Execute => "run the command",
+            Action::NewLine => "insert a new line",
+            Action::HistoryPrev => "go up a line or back in the history",
+            Action::HistoryNext => "go down a line or forward in the history",
+            Action::SearchHistory => "search the history",
+            Action::Complete => "complete the word",
+            Action::CompletePrev => "cycle the completions backwards",
+            Action::Connections => "manage the saved rooms",
+            Action::Connect => "join the selected room",
+            Action::AddEndpoint => "save a room",
+            Action::ScrollCodeLeft => "scroll the code of the selected message left",
+            Action::ScrollCodeRight => "scroll the code of the selected message right",
+            Action::CopyCode => "copy the code of the selected or latest message",
+            Action::Links => "pick a link of the messages",
+            Action::OpenLink => "open the selected link",
+            Action::CopyLink => "copy the selected link",
+            Action::Search => "search the messages",
+            Action::JumpToResult => "show the selected result in the room",
+        })
+    }
+    /// Whether the action makes sense in the context
+    fn allowed_in(self, context: KeyContext) -> bool {
+        match self {
+            Action::CommandMode
+            | Action::TypingMode
+            | Action::Quit
+            | Action::Connections
+            | Action::Links
+            | Action::Search => matches!(context, KeyContext::Normal | KeyContext::Popup),
+            Action::NormalMode => matches!(
+                context,
+                KeyContext::Typing
+                    | KeyContext::Command
+                    | KeyContext::Connections
+                    | KeyContext::Links
+                    | KeyContext::Search
+            ),
+            Action::SelectPrev | Action::SelectNext => matches!(
+                context,
+                KeyContext::Normal
+                    | KeyContext::Connections
+                    | KeyContext::Links
+                    | KeyContext::Search
+            ),
+            Action::Edit | Action::Delete => {
+                matches!(context, KeyContext::Normal | KeyContext::Connections)
+            }
+            Action::Connect | Action::AddEndpoint => context == KeyContext::Connections,
+            Action::OpenLink | Action::CopyLink => context == KeyContext::Links,
+            Action::JumpToResult => context == KeyContext::Search,
+            Action::HistoryPrev
+            | Action::HistoryNext
+            | Action::SearchHistory
+            | Action::Complete
+            | Action::CompletePrev => matches!(context, KeyContext::Typing | KeyContext::Command),
+            Action::Send | Action::NewLine => context == KeyContext::Typing,
+            Action::Execute => context == KeyContext::Command,
+            _ => context == KeyContext::Normal,
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+    fn from_str(name: &str) -> Result<Self, ()> {
+        ACTIONS
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, action)| *action)
+            .ok_or(())
+    }
+}
+
+/// A key along with its modifiers, e.g. "ctrl-r" or "shift-tab"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Shift is part of the character itself, "Q" is "shift-q"
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::Char(c.to_ascii_uppercase()),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            code => Self { code, modifiers },
+        }
+    }
+    /// Whether the chord types a character in the input box
+    fn is_text(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_))
+            && !self
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = ();
+    fn from_str(chord: &str) -> Result<Self, ()> {
+        const MODIFIERS: [(&str, KeyModifiers); 7] = [
+            ("ctrl", KeyModifiers::CONTROL),
+            ("c", KeyModifiers::CONTROL),
+            ("alt", KeyModifiers::ALT),
+            ("meta", KeyModifiers::ALT),
+            ("m", KeyModifiers::ALT),
+            ("shift", KeyModifiers::SHIFT),
+            ("s", KeyModifiers::SHIFT),
+        ];
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = chord;
+        // the key itself may be a "-", e.g. "ctrl--"
+        while let Some((prefix, key)) = rest
+            .split_once(['-', '+'])
+            .filter(|(_, key)| !key.is_empty())
+        {
+            let (_, modifier) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(prefix))
+                .ok_or(())?;
+            modifiers |= *modifier;
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                key => KeyCode::F(
+                    key.strip_prefix('f')
+                        .and_then(|n| n.parse().ok())
+                        .ok_or(())?,
+                ),
+            },
+        };
+        let code = match code {
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            // terminals report control characters in lowercase
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            code => code,
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) || self.code == KeyCode::BackTab {
+            write!(f, "Shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::BackTab => write!(f, "Tab"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Bindings of the key chords to actions, for each context
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyContext, Vec<(KeyChord, Action)>>,
+    /// bindings of the presets the config replaced by other actions, reported on startup
+    pub overridden: Vec<String>,
+}
+
+impl Keymap {
+    /// Builds the keymap described by the config.
+    /// Every invalid or conflicting binding is reported, not only the first one.
+    /// Replacing a binding of the presets is allowed, it is listed in `overridden`.
+    pub fn new(config: &KeysConfig) -> Result<Self, String> {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+            overridden: vec![],
+        };
+        let mut errors = vec![];
+        let tables = [
+            (KeyContext::Normal, &config.normal),
+            (KeyContext::Typing, &config.typing),
+            (KeyContext::Command, &config.command),
+            (KeyContext::Popup, &config.popup),
+            (KeyContext::Connections, &config.connections),
+            (KeyContext::Links, &config.links),
+            (KeyContext::Search, &config.search),
+        ];
+        for (context, table) in tables {
+            for (chord, action) in preset_bindings(KeyPreset::Default, context)
+                .iter()
+                .chain(preset_bindings(config.preset, context))
+            {
+                keymap.bind(context, chord.parse().unwrap(), Some(*action));
+            }
+            // chords written differently may still be the same key
+            let mut seen: HashMap<KeyChord, (&str, &str)> = HashMap::new();
+            for (written, name) in table {
+                let section = format!("keys.{}", context.name());
+                let Ok(chord) = written.parse::<KeyChord>() else {
+                    errors.push(format!("{}: \"{}\" is not a valid key", section, written));
+                    continue;
+                };
+                let action = match name.as_str() {
+                    "none" => None,
+                    name => match name.parse::<Action>() {
+                        Ok(action) if action.allowed_in(context) => Some(action),
+                        Ok(_) => {
+                            errors.push(format!(
+                                "{}: \"{}\" cannot be used in {} mode",
+                                section,
+                                name,
+                                context.name()
+                            ));
+                            continue;
+                        }
+                        Err(()) => {
+                            errors.push(format!("{}: unknown action \"{}\"", section, name));
+                            continue;
+                        }
+                    },
+                };
+                if chord.is_text() && matches!(context, KeyContext::Typing | KeyContext::Command) {
+                    errors.push(format!(
+                        "{}: \"{}\" would prevent typing it, add a modifier",
+                        section, written
+                    ));
+                    continue;
+                }
+                match seen.get(&chord) {
+                    Some((other, other_name)) if *other_name != name => errors.push(format!(
+                        "{}: \"{}\" and \"{}\" are the same key bound to \"{}\" and \"{}\"",
+                        section, other, written, other_name, name
+                    )),
+                    _ => {
+                        seen.insert(chord, (written, name));
+                        match (keymap.bound(context, chord), action) {
+                            (Some(preset), Some(action)) if preset != action => {
+                                keymap.overridden.push(format!(
+                                    "{}: \"{}\" runs \"{}\" instead of \"{}\"",
+                                    section,
+                                    written,
+                                    name,
+                                    preset.name()
+                                ))
+                            }
+                            _ => {}
+                        }
+                        keymap.bind(context, chord, action);
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(keymap)
+        } else {
+            Err(format!("invalid key bindings:\n  {}", errors.join("\n  ")))
+        }
+    }
+    /// Binds a chord, replacing its previous binding, None unbinds it
+    fn bind(&mut self, context: KeyContext, chord: KeyChord, action: Option<Action>) {
+        let bindings = self.bindings.entry(context).or_default();
+        bindings.retain(|(bound, _)| *bound != chord);
+        if let Some(action) = action {
+            bindings.push((chord, action));
+        }
+    }
+    /// The action bound to the key in the context of the input mode
+    pub fn action(&self, mode: &InputMode, key: KeyEvent) -> Option<Action> {
+        self.bound(KeyContext::of(mode), KeyChord::from(key))
+    }
+    fn bound(&self, context: KeyContext, chord: KeyChord) -> Option<Action> {
+        self.bindings
+            .get(&context)?
+            .iter()
+            .find(|(bound, _)| *bound == chord)
+            .map(|(_, action)| *action)
+    }
+    /// The actions of a context along with their chords, in the order of the help
+    pub fn describe(&self, context: KeyContext) -> Vec<(Action, Vec<KeyChord>)> {
+        let bindings = self.bindings.get(&context).cloned().unwrap_or_default();
+        ACTIONS
+            .iter()
+            .map(|(_, action)| {
+                let chords = bindings
+                    .iter()
+                    .filter(|(_, bound)| bound == action)
+                    .map(|(chord, _)| *chord)
+                    .collect::<Vec<_>>();
+                (*action, chords)
+            })
+            .filter(|(_, chords)| !chords.is_empty())
+            .collect()
+    }
+}
+
+/// Bindings of a preset, the vim and emacs ones come on top of the default ones
+fn preset_bindings(preset: KeyPreset, context: KeyContext) -> &'static [(&'static str, Action)] {
+    match (preset, context) {
+        (KeyPreset::Default, KeyContext::Normal) => &[
+            (":", Action::CommandMode),
+            ("t", Action::TypingMode),
+            ("h", Action::Help),
+            ("r", Action::RetryFailed),
+            ("Q", Action::Quit),
+            ("up", Action::SelectPrev),
+            ("k", Action::SelectPrev),
+            ("down", Action::SelectNext),
+            ("j", Action::SelectNext),
+            ("esc", Action::Unselect),
+            ("enter", Action::Reply),
+            ("T", Action::ToggleThread),
+            ("1", Action::QuickReaction(0)),
+            ("2", Action::QuickReaction(1)),
+            ("3", Action::QuickReaction(2)),
+            ("4", Action::QuickReaction(3)),
+            ("5", Action::QuickReaction(4)),
+            ("6", Action::QuickReaction(5)),
+            ("e", Action::Edit),
+            ("d", Action::Delete),
+            ("pageup", Action::ScrollUp),
+            ("pagedown", Action::ScrollDown),
+            ("end", Action::ScrollToBottom),
+            ("left", Action::ScrollCodeLeft),
+            ("right", Action::ScrollCodeRight),
+            ("y", Action::CopyCode),
+            ("u", Action::Links),
+            ("/", Action::Search),
+            ("c", Action::Connections),
+        ],
+        (KeyPreset::Default, KeyContext::Typing) => &[
+            ("esc", Action::NormalMode),
+            ("enter", Action::Send),
+            ("shift-enter", Action::NewLine),
+            ("alt-enter", Action::NewLine),
+            ("up", Action::HistoryPrev),
+            ("down", Action::HistoryNext),
+            ("ctrl-r", Action::SearchHistory),
+            ("tab", Action::Complete),
+            ("shift-tab", Action::CompletePrev),
+        ],
+        (KeyPreset::Default, KeyContext::Command) => &[
+            ("esc", Action::NormalMode),
+            ("enter", Action::Execute),
+            ("up", Action::HistoryPrev),
+            ("down", Action::HistoryNext),
+            ("ctrl-r", Action::SearchHistory),
+            ("tab", Action::Complete),
+            ("shift-tab", Action::CompletePrev),
+        ],
+        (KeyPreset::Default, KeyContext::Popup) => &[
+            (":", Action::CommandMode),
+            ("t", Action::TypingMode),
+            ("Q", Action::Quit),
+        ],
+        (KeyPreset::Default, KeyContext::Connections) => &[
+            ("esc", Action::NormalMode),
+            ("up", Action::SelectPrev),
+            ("k", Action::SelectPrev),
+            ("down", Action::SelectNext),
+            ("j", Action::SelectNext),
+            ("enter", Action::Connect),
+            ("a", Action::AddEndpoint),
+            ("e", Action::Edit),
+            ("d", Action::Delete),
+        ],
+        (KeyPreset::Default, KeyContext::Links) => &[
+            ("esc", Action::NormalMode),
+            ("up", Action::SelectPrev),
+            ("k", Action::SelectPrev),
+            ("down", Action::SelectNext),
+            ("j", Action::SelectNext),
+            ("enter", Action::OpenLink),
+            ("y", Action::CopyLink),
+        ],
+        (KeyPreset::Default, KeyContext::Search) => &[
+            ("esc", Action::NormalMode),
+            ("up", Action::SelectPrev),
+            ("down", Action::SelectNext),
+            ("enter", Action::JumpToResult),
+        ],
+        (KeyPreset::Vim, KeyContext::Normal) => &[
+            ("i", Action::TypingMode),
+            ("a", Action::TypingMode),
+            ("?", Action::Help),
+            ("ctrl-u", Action::ScrollUp),
+            ("ctrl-b", Action::ScrollUp),
+            ("ctrl-d", Action::ScrollDown),
+            ("ctrl-f", Action::ScrollDown),
+            ("G", Action::ScrollToBottom),
+            ("x", Action::Delete),
+        ],
+        (KeyPreset::Vim, KeyContext::Typing | KeyContext::Command) => {
+            &[("ctrl-c", Action::NormalMode)]
+        }
+        (KeyPreset::Vim, KeyContext::Popup) => &[("i", Action::TypingMode)],
+        (KeyPreset::Vim, KeyContext::Connections) => &[("x", Action::Delete)],
+        (KeyPreset::Vim, KeyContext::Links) => &[],
+        (KeyPreset::Vim, KeyContext::Search) => &[("ctrl-c", Action::NormalMode)],
+        (KeyPreset::Emacs, KeyContext::Normal) => &[
+            ("ctrl-p", Action::SelectPrev),
+            ("ctrl-n", Action::SelectNext),
+            ("ctrl-g", Action::Unselect),
+            ("alt-v", Action::ScrollUp),
+            ("ctrl-v", Action::ScrollDown),
+            ("alt->", Action::ScrollToBottom),
+            ("ctrl-x", Action::CommandMode),
+            ("ctrl-s", Action::Search),
+        ],
+        (KeyPreset::Emacs, KeyContext::Typing | KeyContext::Command) => &[
+            ("ctrl-g", Action::NormalMode),
+            ("ctrl-p", Action::HistoryPrev),
+            ("ctrl-n", Action::HistoryNext),
+        ],
+        (KeyPreset::Emacs, KeyContext::Popup) => &[("ctrl-x", Action::CommandMode)],
+        (KeyPreset::Emacs, KeyContext::Connections | KeyContext::Links | KeyContext::Search) => &[
+            ("ctrl-p", Action::SelectPrev),
+            ("ctrl-n", Action::SelectNext),
+            ("ctrl-g", Action::NormalMode),
+        ],
+    }
+}
@@ -0,0 +1,388 @@
+use std::ops::Range;
+
+/// fence opening and closing a code block
+const FENCE: &str = "```";
+/// delimiters of the inline formatting, the longer ones first so "**" is not read as two "*"
+const DELIMITERS: [(&str, Emphasis); 3] = [
+    ("**", Emphasis::Bold),
+    ("~~", Emphasis::Strike),
+    ("*", Emphasis::Italic),
+];
+/// characters ending a sentence rather than the URL they follow
+const URL_TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '>', '\'', '"'];
+
+/// A part of the content of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block<'a> {
+    Text(&'a str),
+    /// the lines between "```lang" and "```", a block left open runs to the end of the message
+    Code {
+        lang: &'a str,
+        code: &'a str,
+    },
+}
+
+/// Splits the content into text and fenced code blocks.
+/// Blank text between blocks is dropped, content without any block is a single text block.
+pub fn blocks(content: &str) -> Vec<Block<'_>> {
+    let mut blocks = vec![];
+    // start of the text, or of the code along with its language, being read
+    let mut text_start = 0;
+    let mut code: Option<(&str, usize)> = None;
+    let mut line_start = 0;
+    for line in content.split_inclusive('\n') {
+        let next_line = line_start + line.len();
+        let trimmed = line.trim();
+        match code {
+            Some((lang, code_start)) if trimmed == FENCE => {
+                blocks.push(Block::Code {
+                    lang,
+                    code: content[code_start..line_start].trim_end_matches('\n'),
+                });
+                code = None;
+                text_start = next_line;
+            }
+            None => {
+                // "```inline```" is left to the inline markup
+                if let Some(lang) = trimmed
+                    .strip_prefix(FENCE)
+                    .filter(|lang| !lang.contains('`'))
+                {
+                    push_text(&mut blocks, &content[text_start..line_start]);
+                    code = Some((lang.trim(), next_line));
+                }
+            }
+            Some(_) => {}
+        }
+        line_start = next_line;
+    }
+    match code {
+        Some((lang, code_start)) => blocks.push(Block::Code {
+            lang,
+            code: content[code_start..].trim_end_matches('\n'),
+        }),
+        None => push_text(&mut blocks, &content[text_start..]),
+    }
+    if blocks.is_empty() {
+        blocks.push(Block::Text(""));
+    }
+    blocks
+}
+
+/// The code of the fenced blocks of the content, in order
+pub fn code_blocks(content: &str) -> impl Iterator<Item = &str> {
+    blocks(content).into_iter().filter_map(|block| match block {
+        Block::Code { code, .. } => Some(code),
+        Block::Text(_) => None,
+    })
+}
+
+fn push_text<'a>(blocks: &mut Vec<Block<'a>>, text: &'a str) {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    if !text.trim().is_empty() {
+        blocks.push(Block::Text(text));
+    }
+}
+
+/// Formatting of a piece of text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Format {
+    pub bold: bool,
+    pub italic: bool,
+    pub strike: bool,
+    pub code: bool,
+    pub link: bool,
+}
+
+/// A piece of a line, the range being the one of its text without the delimiters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inline {
+    pub range: Range<usize>,
+    pub format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emphasis {
+    Bold,
+    Strike,
+    Italic,
+}
+
+impl Format {
+    fn emphasis(&mut self, emphasis: Emphasis) -> &mut bool {
+        match emphasis {
+            Emphasis::Bold => &mut self.bold,
+            Emphasis::Strike => &mut self.strike,
+            Emphasis::Italic => &mut self.italic,
+        }
+    }
+}
+
+/// The text of a "> quote" line
+pub fn quote(line: &str) -> Option<&str> {
+    line.strip_prefix("> ").or((line == ">").then_some(""))
+}
+
+/// Splits a line into formatted pieces: *italic*, **bold**, ~~strike~~, `code` and URLs.
+/// Code and URLs are never formatted further, a delimiter without a closing one is kept as text.
+/// Runs in linear time, whatever the line: once a delimiter is found unclosed none of the
+/// following ones can be closed either, so the line is searched at most once per delimiter.
+pub fn inlines(line: &str) -> Vec<Inline> {
+    let urls = find_urls(line);
+    let mut urls = urls.iter().peekable();
+    let mut pieces = vec![];
+    let mut format = Format::default();
+    // where the open emphases close, and the delimiters known to be unclosed
+    let mut closing: [Option<usize>; 3] = [None; 3];
+    let mut unclosed = [false; 4];
+    let mut start = 0;
+    let mut i = 0;
+    let push = |pieces: &mut Vec<Inline>, range: Range<usize>, format: Format| {
+        if !range.is_empty() {
+            pieces.push(Inline { range, format });
+        }
+    };
+    while i < line.len() {
+        if let Some(url) = urls.next_if(|url| url.start == i) {
+            push(&mut pieces, start..i, format);
+            push(
+                &mut pieces,
+                url.clone(),
+                Format {
+                    link: true,
+                    ..format
+                },
+            );
+            (i, start) = (url.end, url.end);
+            continue;
+        }
+        let rest = &line[i..];
+        if rest.starts_with('`') && !unclosed[3] {
+            match rest[1..].find('`') {
+                Some(len) if len > 0 => {
+                    push(&mut pieces, start..i, format);
+                    push(
+                        &mut pieces,
+                        i + 1..i + 1 + len,
+                        Format {
+                            code: true,
+                            ..format
+                        },
+                    );
+                    i += len + 2;
+                    start = i;
+                    continue;
+                }
+                Some(_) => {}
+                None => unclosed[3] = true,
+            }
+        }
+        if let Some((k, (delimiter, emphasis))) = DELIMITERS
+            .iter()
+            .enumerate()
+            .find(|(_, (delimiter, _))| rest.starts_with(delimiter))
+        {
+            let after = i + delimiter.len();
+            let toggled = if closing[k] == Some(i) {
+                closing[k] = None;
+                true
+            } else if !*format.emphasis(*emphasis) && !unclosed[k] {
+                match find_closing(line, after, delimiter) {
+                    Some(end) => {
+                        closing[k] = Some(end);
+                        true
+                    }
+                    None => {
+                        unclosed[k] = true;
+                        false
+                    }
+                }
+            } else {
+                false
+            };
+            if toggled {
+                push(&mut pieces, start..i, format);
+                let on = format.emphasis(*emphasis);
+                *on = !*on;
+                (i, start) = (after, after);
+                continue;
+            }
+            // a "**" that does not toggle is text, not two "*"
+            i = after;
+            continue;
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    push(&mut pieces, start..line.len(), format);
+    pieces
+}
+
+/// Where the emphasis opened before `from` closes: the next delimiter following some text,
+/// not preceded by a space and, for single ones, not part of a longer run
+fn find_closing(line: &str, from: usize, delimiter: &str) -> Option<usize> {
+    if line[from..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    let first = delimiter.chars().next()?;
+    line[from..]
+        .match_indices(delimiter)
+        .map(|(pos, _)| from + pos)
+        .find(|&pos| {
+            let before = line[..pos].chars().next_back();
+            let after = line[pos + delimiter.len()..].chars().next();
+            pos > from
+                && !before.is_some_and(char::is_whitespace)
+                && (delimiter.len() > 1 || (before != Some(first) && after != Some(first)))
+        })
+}
+
+/// Byte ranges of the http and https URLs of the text, without the punctuation following them
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = vec![];
+    let mut from = 0;
+    while let Some(pos) = text[from..].find("http") {
+        let start = from + pos;
+        let rest = &text[start..];
+        let scheme = ["https://", "http://"]
+            .into_iter()
+            .find(|scheme| rest.starts_with(scheme));
+        let at_word_start = !text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric());
+        from = start + 4;
+        let Some(scheme) = scheme.filter(|_| at_word_start) else {
+            continue;
+        };
+        // the URL runs to the next space, where the search resumes
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = rest[..len].trim_end_matches(URL_TRAILING);
+        if url.len() > scheme.len() {
+            urls.push(start..start + url.len());
+        }
+        from = start + len;
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pieces of a line, each marked with the formats it has: b, i, s, c and l
+    fn pieces(line: &str) -> Vec<(&str, String)> {
+        inlines(line)
+            .into_iter()
+            .map(|inline| {
+                let format = inline.format;
+                let marks = [
+                    (format.bold, 'b'),
+                    (format.italic, 'i'),
+                    (format.strike, 's'),
+                    (format.code, 'c'),
+                    (format.link, 'l'),
+                ];
+                let marks = marks.iter().filter(|(on, _)| *on).map(|(_, mark)| *mark);
+                (&line[inline.range], marks.collect())
+            })
+            .collect()
+    }
+
+    fn marked<'a>(expected: &[(&'a str, &str)]) -> Vec<(&'a str, String)> {
+        expected
+            .iter()
+            .map(|(text, marks)| (*text, marks.to_string()))
+            .collect()
+    }
+
+    fn plain(text: &str) -> Vec<(&str, String)> {
+        marked(&[(text, "")])
+    }
+
+    #[test]
+    fn formats_closed_markers() {
+        assert_eq!(
+            pieces("**bold** *it* ~~gone~~ `x`"),
+            marked(&[
+                ("bold", "b"),
+                (" ", ""),
+                ("it", "i"),
+                (" ", ""),
+                ("gone", "s"),
+                (" ", ""),
+                ("x", "c"),
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_unbalanced_markers_as_text() {
+        for line in [
+            "**open",
+            "*open",
+            "~~open",
+            "`open",
+            "open**",
+            "a * b",
+            "2 * 3 * 4",
+        ] {
+            assert_eq!(pieces(line), plain(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn closes_what_can_be_closed() {
+        assert_eq!(pieces("**a *b**"), marked(&[("a *b", "b")]));
+        assert_eq!(pieces("*a **b*"), marked(&[("a **b", "i")]));
+    }
+
+    #[test]
+    fn nests_markers() {
+        assert_eq!(
+            pieces("**bold *both* ~~all~~**"),
+            marked(&[("bold ", "b"), ("both", "bi"), (" ", "b"), ("all", "bs")])
+        );
+    }
+
+    #[test]
+    fn leaves_code_and_urls_unformatted() {
+        assert_eq!(
+            pieces("`*a*` https://x.org/*a*"),
+            marked(&[("*a*", "c"), (" ", ""), ("https://x.org/*a*", "l")])
+        );
+    }
+
+    #[test]
+    fn runs_in_linear_time_on_unclosed_markers() {
+        let line = "*a ".repeat(20_000) + &"**b ".repeat(20_000);
+        assert_eq!(pieces(&line), plain(&line));
+    }
+
+    #[test]
+    fn splits_code_blocks() {
+        let content = "before\n```rust\nfn main() {}\n```\nafter";
+        assert_eq!(
+            blocks(content),
+            [
+                Block::Text("before"),
+                Block::Code {
+                    lang: "rust",
+                    code: "fn main() {}"
+                },
+                Block::Text("after"),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_an_unclosed_code_block_to_the_end() {
+        assert_eq!(
+            blocks("```\nlet a = 1;\n"),
+            [Block::Code {
+                lang: "",
+                code: "let a = 1;"
+            }]
+        );
+        assert_eq!(blocks("```inline```"), [Block::Text("```inline```")]);
+    }
+}
@@ -0,0 +1,44 @@
+use std::ops::Range;
+
+/// Byte ranges of the whole-word occurrences of the terms in the text, ignoring ASCII case
+pub fn find_terms(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    find(text, terms, true)
+}
+
+/// Byte ranges of the occurrences of the terms in the text, within words too, ignoring ASCII case
+pub fn find_substrings(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    find(text, terms, false)
+}
+
+fn find(text: &str, terms: &[String], whole_words: bool) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    for term in terms.iter().filter(|term| !term.is_empty()) {
+        let mut start = 0;
+        while let Some(pos) = find_ignore_case(&text[start..], term) {
+            let (begin, end) = (start + pos, start + pos + term.len());
+            let before = text[..begin].chars().next_back();
+            let after = text[end..].chars().next();
+            if !whole_words || !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            {
+                ranges.push(begin..end);
+            }
+            start = end;
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+    // keep the first of overlapping matches
+    ranges.dedup_by(|next, prev| next.start < prev.end);
+    ranges
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.char_indices().map(|(i, _)| i).find(|&i| {
+        haystack
+            .get(i..i + needle.len())
+            .is_some_and(|candidate| candidate.eq_ignore_ascii_case(needle))
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -1,62 +1,157 @@
+use chrono::{DateTime, Local, TimeZone, Utc};
 use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, time::Instant};
 
-#[derive(Debug, Clone)]
+/// Where a message sent by the root user stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// waiting for the host to acknowledge it since the attached instant
+    Pending(Instant),
+    Delivered,
+    Failed,
+}
+
+/// Users who reacted to a message with the same emoji
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub users: Vec<String>,
+}
+
+/// What a link of a message leads to, fetched by the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// 128 random bits from the OS, in hex, so the ids of messages and files cannot be guessed
+pub fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the OS random source is unavailable");
+    bytes
+        .iter()
+        .fold(String::with_capacity(32), |mut id, byte| {
+            let _ = write!(id, "{:02x}", byte);
+            id
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
+    /// generated by the author's client, unique within a room
+    #[serde(default)]
+    pub id: String,
     pub content: String,
     pub color: Color,
     pub source: String,
+    /// milliseconds since the unix epoch, stamped by the sender and normalized by the host
+    #[serde(default)]
+    pub timestamp: i64,
+    /// id of the message this one answers to
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    /// only tracked locally, for the messages of the root user
+    #[serde(skip)]
+    pub status: Option<DeliveryStatus>,
+    /// only tracked locally, set when the message mentions the root user or a highlight word
+    #[serde(skip)]
+    pub highlighted: bool,
+    /// set by the peers that had to sanitize the content, it held control characters or the like
+    #[serde(default)]
+    pub suspicious: bool,
+    /// sent by the host in frames of their own, once fetched
+    #[serde(skip)]
+    pub previews: Vec<LinkPreview>,
 }
 
 impl Message {
     pub fn new(content: String, color: Color, source: String) -> Self {
         Self {
+            id: random_id(),
             content,
             color,
             source,
+            timestamp: Utc::now().timestamp_millis(),
+            reply_to: None,
+            edited: false,
+            deleted: false,
+            reactions: vec![],
+            status: None,
+            highlighted: false,
+            suspicious: false,
+            previews: vec![],
         }
     }
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-        // handle serialization
-        // bytes.extend(self.user_id.to_be_bytes());
-        bytes.extend(self.content.as_bytes());
-        bytes
-    }
-    pub fn from_bytes(data: &[u8]) -> Self {
-        // Ensure sufficient data for deserialization
-        if data.len() < std::mem::size_of::<usize>() + std::mem::size_of::<u16>() {
-            panic!("Insufficient data to deserialize Message");
+    /// The time the message was sent, in the local timezone
+    pub fn local_time(&self) -> DateTime<Local> {
+        Local
+            .timestamp_millis_opt(self.timestamp)
+            .single()
+            .unwrap_or_else(Local::now)
+    }
+    /// A message generated locally to inform the user about the state of the room
+    pub fn notice(content: String) -> Self {
+        Self {
+            // notices never travel, they need no id
+            id: String::new(),
+            ..Self::new(content, Color::DarkGray, String::from("*"))
         }
-
-        // Extract user_id using big-endian byte order
-        let user_id =
-            usize::from_be_bytes(data[..std::mem::size_of::<usize>()].try_into().unwrap());
-
-        // Extract content length using big-endian byte order
-        let content_len = u16::from_be_bytes(
-            data[std::mem::size_of::<usize>()
-                ..std::mem::size_of::<usize>() + std::mem::size_of::<u16>()]
-                .try_into()
-                .unwrap(),
-        );
-
-        // Ensure data has enough bytes for the content
-        if data.len()
-            < std::mem::size_of::<usize>() + std::mem::size_of::<u16>() + content_len as usize
-        {
-            panic!("Insufficient data for content in Message");
+    }
+    pub fn is_notice(&self) -> bool {
+        self.id.is_empty()
+    }
+    /// The beginning of the content, used to quote the message
+    pub fn snippet(&self, max_chars: usize) -> String {
+        if self.deleted {
+            return String::from("[deleted]");
         }
-
-        // Extract content as a string
-        let content = String::from_utf8_lossy(
-            &data[std::mem::size_of::<usize>() + std::mem::size_of::<u16>()..],
-        );
-
-        // Use a default color for now
-        Self {
-            content: content.to_string(),
-            color: Color::LightYellow,
-            source: String::from("Program"),
+        // quotes and titles hold a single line
+        let mut snippet = self
+            .content
+            .chars()
+            .take(max_chars)
+            .map(|c| if c == '\n' { ' ' } else { c })
+            .collect::<String>();
+        if self.content.chars().count() > max_chars {
+            snippet.push('…');
         }
+        snippet
+    }
+    pub fn has_reacted(&self, emoji: &str, user: &str) -> bool {
+        self.reactions
+            .iter()
+            .any(|reaction| reaction.emoji == emoji && reaction.users.iter().any(|u| u == user))
+    }
+    /// Adds or removes the reaction of a user, empty reactions are dropped
+    pub fn set_reaction(&mut self, emoji: &str, user: &str, add: bool) {
+        match self.reactions.iter_mut().find(|r| r.emoji == emoji) {
+            Some(reaction) => {
+                reaction.users.retain(|u| u != user);
+                if add {
+                    reaction.users.push(user.to_owned());
+                }
+            }
+            None if add => self.reactions.push(Reaction {
+                emoji: emoji.to_owned(),
+                users: vec![user.to_owned()],
+            }),
+            None => {}
+        }
+        self.reactions.retain(|reaction| !reaction.users.is_empty());
+    }
+    /// Whether the message was written by the root user, whose names are unique within a room.
+    /// The delivery status is not kept with the messages logged, it cannot tell.
+    pub fn is_own(&self, root_user: &str) -> bool {
+        !self.is_notice() && self.source == root_user
     }
 }
@@ -1,5 +1,18 @@
 pub mod commands;
+pub mod completion;
+pub mod config;
+pub mod connection;
+pub mod editor;
+pub mod emoji;
+pub mod endpoints;
+pub mod history;
+pub mod keymap;
+pub mod markdown;
+pub mod mentions;
 pub mod message;
 pub mod modes;
+pub mod room_log;
+pub mod search;
 pub mod session;
+pub mod transfer;
 pub mod user;
@@ -1,15 +1,19 @@
+#[derive(Default)]
 pub enum InputMode {
     Normal,
     Typing,
     Command,
+    #[default]
     Help,
     Info(String),
-}
-
-impl Default for InputMode {
-    fn default() -> Self {
-        Self::Help
-    }
+    /// the connection manager, listing the saved and recent rooms
+    Connections,
+    /// the rooms found on the local network
+    Discovery,
+    /// the links of the messages
+    Links,
+    /// the messages matching a search
+    Search,
 }
 
 impl std::fmt::Display for InputMode {
@@ -19,6 +23,10 @@ impl std::fmt::Display for InputMode {
             Self::Typing => write!(f, " Typing Mode "),
             Self::Command => write!(f, " Command Mode "),
             Self::Help => write!(f, " Help "),
+            Self::Connections => write!(f, " Connections "),
+            Self::Discovery => write!(f, " Discovery "),
+            Self::Links => write!(f, " Links "),
+            Self::Search => write!(f, " Search "),
         }
     }
 }
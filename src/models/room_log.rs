@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::services::protocol::Frame;
+
+use super::config::data_dir;
+use super::message::Message;
+
+const LOGS_DIR: &str = "logs";
+
+/// The messages of a room, kept across restarts as the frames that made them:
+/// one JSON frame per line, edits, deletions, reactions and acknowledgements being appended
+/// after the messages they change
+#[derive(Debug, Clone)]
+pub struct RoomLog {
+    path: PathBuf,
+}
+
+impl RoomLog {
+    /// The log of the room behind a link, e.g. `<data dir>/endl-rc/logs/192.168.1.10_8080.jsonl`
+    pub fn of_room(link: &str) -> Self {
+        let name = link
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        Self {
+            path: data_dir().join(LOGS_DIR).join(format!("{}.jsonl", name)),
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Appends a frame changing the messages, the other frames are not kept
+    pub fn append(&self, frame: &Frame) -> io::Result<()> {
+        if !matches!(
+            frame,
+            Frame::Chat(_)
+                | Frame::Edit { .. }
+                | Frame::Delete { .. }
+                | Frame::React { .. }
+                | Frame::Ack {
+                    timestamp: Some(_),
+                    ..
+                }
+        ) {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&frame.encode())
+    }
+    /// The messages of the room, oldest first, see `read`
+    pub fn messages(&self) -> io::Result<Vec<Message>> {
+        match read(&self.path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            result => result,
+        }
+    }
+}
+
+/// Reads the messages of a log file, oldest first, with their edits, deletions and reactions
+/// applied.
+/// Messages sent again are kept once, malformed lines are skipped.
+pub fn read(path: &Path) -> io::Result<Vec<Message>> {
+    let contents = fs::read_to_string(path)?;
+    let mut messages: Vec<Message> = vec![];
+    let mut by_id = HashMap::new();
+    for frame in contents.lines().filter_map(Frame::decode) {
+        match frame {
+            Frame::Chat(msg) if !by_id.contains_key(&msg.id) => {
+                by_id.insert(msg.id.clone(), messages.len());
+                messages.push(msg);
+            }
+            Frame::Edit {
+                id,
+                content,
+                suspicious,
+            } => {
+                if let Some(msg) = by_id.get(&id).map(|&i| &mut messages[i]) {
+                    if !msg.deleted {
+                        msg.content = content;
+                        msg.edited = true;
+                        msg.suspicious |= suspicious;
+                    }
+                }
+            }
+            Frame::Delete { id } => {
+                if let Some(msg) = by_id.get(&id).map(|&i| &mut messages[i]) {
+                    msg.content.clear();
+                    msg.deleted = true;
+                }
+            }
+            Frame::React {
+                id,
+                emoji,
+                name,
+                add,
+            } => {
+                if let Some(msg) = by_id.get(&id).map(|&i| &mut messages[i]) {
+                    msg.set_reaction(&emoji, &name, add);
+                }
+            }
+            // the messages of the local user, as stamped by the host
+            Frame::Ack {
+                id,
+                timestamp: Some(timestamp),
+            } => {
+                if let Some(msg) = by_id.get(&id).map(|&i| &mut messages[i]) {
+                    msg.timestamp = timestamp;
+                }
+            }
+            _ => {}
+        }
+    }
+    messages.sort_by_key(|msg| msg.timestamp);
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+    use crate::models::message::random_id;
+    use crate::services::export::{transcript, ExportFormat};
+
+    fn log() -> RoomLog {
+        RoomLog {
+            path: std::env::temp_dir().join(format!("endl-rc-{}.jsonl", random_id())),
+        }
+    }
+
+    fn react(id: &str, emoji: &str, name: &str, add: bool) -> Frame {
+        Frame::React {
+            id: id.to_owned(),
+            emoji: emoji.to_owned(),
+            name: name.to_owned(),
+            add,
+        }
+    }
+
+    #[test]
+    fn keeps_reactions_through_export() {
+        let log = log();
+        let msg = Message::new("hi".to_owned(), Color::Red, "alice".to_owned());
+        let id = msg.id.clone();
+        log.append(&Frame::Chat(msg)).unwrap();
+        log.append(&react(&id, "👍", "bob", true)).unwrap();
+        log.append(&react(&id, "🎉", "bob", true)).unwrap();
+        log.append(&react(&id, "👍", "carol", true)).unwrap();
+        log.append(&react(&id, "🎉", "bob", false)).unwrap();
+        let messages = log.messages();
+        fs::remove_file(log.path()).unwrap();
+
+        let messages = messages.unwrap();
+        assert_eq!(messages.len(), 1);
+        let reactions = &messages[0].reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].users, ["bob", "carol"]);
+
+        let json = transcript(&messages, ExportFormat::Json, "room");
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            exported[0]["reactions"],
+            serde_json::json!([{ "emoji": "👍", "users": ["bob", "carol"] }])
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{Days, Local, NaiveDate};
+
+use super::editor::Editor;
+use super::mentions::find_substrings;
+use super::message::Message;
+
+/// What the messages are searched for: words they all contain, ignoring case,
+/// and filters written "from:<user>", "on:<date>", "after:<date>" and "before:<date>",
+/// the dates being "YYYY-MM-DD", "today" or "yesterday" and the bounds included
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    from: Option<String>,
+    after: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut query = Self::default();
+        for word in input.split_whitespace() {
+            let Some((filter, value)) = word.split_once(':').filter(|(_, value)| !value.is_empty())
+            else {
+                query.terms.push(word.to_owned());
+                continue;
+            };
+            match filter {
+                "from" => query.from = Some(value.trim_start_matches('@').to_owned()),
+                "on" => {
+                    let day = parse_date(value)?;
+                    (query.after, query.before) = (Some(day), Some(day));
+                }
+                "after" => query.after = Some(parse_date(value)?),
+                "before" => query.before = Some(parse_date(value)?),
+                // "https://..." and the like are searched as is
+                _ => query.terms.push(word.to_owned()),
+            }
+        }
+        Ok(query)
+    }
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    pub fn matches(&self, msg: &Message) -> bool {
+        let day = msg.local_time().date_naive();
+        !msg.is_notice()
+            && !msg.deleted
+            && self
+                .from
+                .as_ref()
+                .is_none_or(|from| msg.source.eq_ignore_ascii_case(from))
+            && self.after.is_none_or(|after| day >= after)
+            && self.before.is_none_or(|before| day <= before)
+            && self
+                .terms
+                .iter()
+                .all(|term| !find_substrings(&msg.content, std::slice::from_ref(term)).is_empty())
+    }
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    let today = Local::now().date_naive();
+    match value {
+        "today" => Ok(today),
+        "yesterday" => Ok(today - Days::new(1)),
+        _ => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("invalid date \"{}\", expected YYYY-MM-DD", value)),
+    }
+}
+
+/// State of the message search, the pane only showing the messages found
+#[derive(Debug, Clone, Default)]
+pub struct MessageSearch {
+    pub input: Editor,
+    pub query: SearchQuery,
+    /// why the input is not a valid query
+    pub error: Option<String>,
+    /// indexes of the messages found, oldest first
+    pub hits: Vec<usize>,
+    /// index of the selected hit
+    pub selected: usize,
+}
+
+impl MessageSearch {
+    /// Searches the messages again for the input, the newest hit being selected
+    pub fn refresh(&mut self, messages: &[Message]) {
+        match SearchQuery::parse(&self.input.value()) {
+            Ok(query) => {
+                self.hits = if query.is_empty() {
+                    vec![]
+                } else {
+                    (0..messages.len())
+                        .filter(|&i| query.matches(&messages[i]))
+                        .collect()
+                };
+                self.query = query;
+                self.error = None;
+            }
+            // the previous results are kept while the date is being typed
+            Err(e) => self.error = Some(e),
+        }
+        self.selected = self.hits.len().saturating_sub(1);
+    }
+    /// Index of the message of the selected hit
+    pub fn selected_msg(&self) -> Option<usize> {
+        self.hits.get(self.selected).copied()
+    }
+    pub fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.hits.len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    /// A message sent at noon on the day, local time
+    fn message(source: &str, content: &str, day: &str) -> Message {
+        let mut msg = Message::new(content.to_owned(), Color::Reset, source.to_owned());
+        let noon = date(day).and_hms_opt(12, 0, 0).unwrap();
+        msg.timestamp = noon.and_local_timezone(Local).unwrap().timestamp_millis();
+        msg
+    }
+
+    fn search(input: &str) -> SearchQuery {
+        SearchQuery::parse(input).unwrap()
+    }
+
+    #[test]
+    fn parses_terms_and_filters() {
+        let query = search("deploy from:@alice after:2024-03-01 fix");
+        assert_eq!(query.terms, ["deploy", "fix"]);
+        assert_eq!(query.from.as_deref(), Some("alice"));
+        assert_eq!(query.after, Some(date("2024-03-01")));
+        assert_eq!(query.before, None);
+        assert!(search("  ").is_empty());
+    }
+
+    #[test]
+    fn searches_filters_without_value_and_urls_as_terms() {
+        assert_eq!(search("from: on:").terms, ["from:", "on:"]);
+        assert_eq!(search("https://x.org").terms, ["https://x.org"]);
+        assert_eq!(search("todo:later").terms, ["todo:later"]);
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for input in ["on:2024-13-01", "after:03/01/2024", "before:tomorrow"] {
+            assert!(SearchQuery::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn reads_relative_dates() {
+        let today = Local::now().date_naive();
+        assert_eq!(search("after:today").after, Some(today));
+        assert_eq!(search("before:yesterday").before, today.pred_opt());
+    }
+
+    #[test]
+    fn matches_every_term_ignoring_case() {
+        let msg = message("bob", "Deploy the FIX now", "2024-03-05");
+        assert!(search("fix deploy").matches(&msg));
+        assert!(!search("fix rollback").matches(&msg));
+    }
+
+    #[test]
+    fn matches_the_author() {
+        let msg = message("Alice", "hi", "2024-03-05");
+        assert!(search("from:alice").matches(&msg));
+        assert!(search("from:@ALICE").matches(&msg));
+        assert!(!search("from:ali").matches(&msg));
+    }
+
+    #[test]
+    fn matches_dates_bounds_included() {
+        let msg = message("bob", "hi", "2024-03-05");
+        assert!(search("on:2024-03-05").matches(&msg));
+        assert!(!search("on:2024-03-06").matches(&msg));
+        assert!(search("after:2024-03-05 before:2024-03-05").matches(&msg));
+        assert!(!search("after:2024-03-06").matches(&msg));
+        assert!(!search("before:2024-03-04").matches(&msg));
+    }
+
+    #[test]
+    fn skips_notices_and_deleted_messages() {
+        assert!(!search("joined").matches(&Message::notice(String::from("bob joined"))));
+        let mut msg = message("bob", "secret", "2024-03-05");
+        msg.deleted = true;
+        assert!(!search("from:bob").matches(&msg));
+    }
+
+    #[test]
+    fn keeps_the_hits_while_a_date_is_typed() {
+        let messages = [message("bob", "hi", "2024-03-05")];
+        let mut search = MessageSearch::default();
+        search.input.set_value("hi");
+        search.refresh(&messages);
+        assert_eq!(search.hits, [0]);
+        search.input.set_value("hi on:2024-03");
+        search.refresh(&messages);
+        assert!(search.error.is_some());
+        assert_eq!(search.selected_msg(), Some(0));
+    }
+}
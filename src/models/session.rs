@@ -1,38 +1,218 @@
-use crate::services::server_commands::ServerCommand;
+use crate::services::{
+    discovery::{discover, DiscoveredRoom},
+    export::{transcript, ExportFormat},
+    preview::{fetch_preview, is_allowed},
+    protocol::Frame,
+    server_commands::ServerCommand,
+    server_events::ServerEvent,
+    transfer::{checksum as transfer_checksum, decode_base64, send_file, TransferUpdate, Upload},
+};
+use crate::views::{
+    colors::{readable, user_color},
+    theme::Theme,
+};
 
-use super::commands::Command;
-use super::message::Message;
+use super::commands::{find_command, parse_command, ArgKind, COMMANDS};
+use super::completion::Completion;
+use super::config::Config;
+use super::connection::ConnectionState;
+use super::editor::Editor;
+use super::emoji::shortcodes;
+use super::endpoints::Endpoints;
+use super::history::{History, HistorySearch, InputHistory};
+use super::keymap::Keymap;
+use super::markdown::{code_blocks, find_urls};
+use super::mentions::find_terms;
+use super::message::{random_id, DeliveryStatus, LinkPreview, Message};
 use super::modes::InputMode;
+use super::room_log::RoomLog;
+use super::search::MessageSearch;
+use super::transfer::{format_size, unique_path, Direction, Transfer, TransferState};
 use super::user::User;
-use ratatui::style::Color;
-use tokio::sync::{broadcast, watch};
-use tui_input::Input;
+use chrono::Local;
+use crossterm::event::KeyEvent;
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+    oneshot::{self, error::TryRecvError},
+    watch,
+};
+
+/// minimum delay between two "typing" signals sent while editing
+const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+/// editing pause after which the user is considered to have stopped typing
+const TYPING_IDLE: Duration = Duration::from_secs(5);
+/// remote typers are forgotten if no signal refreshes them in time
+const TYPING_EXPIRY: Duration = Duration::from_secs(6);
+/// a message the host did not acknowledge in time is considered failed
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// how long the announcements of the rooms are listened to
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+/// events and frames buffered between the app and the server
+const CHANNEL_CAPACITY: usize = 64;
+/// columns the code blocks are scrolled by at a time
+const CODE_SCROLL_STEP: usize = 8;
+/// links of a message previewed at most
+const MAX_PREVIEWS: usize = 3;
 
 pub struct Session {
+    pub config: Config,
+    pub keymap: Keymap,
+    pub theme: Theme,
     pub input_mode: InputMode,
     users: Vec<User>,
     pub messages: Vec<Message>,
-    pub text_buffer: Input,
-    outgoing_messages_tx: broadcast::Sender<Message>,
-    incoming_messages_rx: broadcast::Receiver<Message>,
+    /// index of the message selected in Normal mode
+    pub selected: Option<usize>,
+    /// lines the message pane is scrolled back from the newest message
+    pub scroll_back: usize,
+    /// columns the code blocks of the selected message are scrolled by
+    pub code_scroll: usize,
+    /// show the messages as raw text rather than formatted
+    pub raw: bool,
+    /// users whose messages are shown as raw text
+    pub raw_users: HashSet<String>,
+    // id of the message being edited in Typing mode
+    editing: Option<String>,
+    // id of the message the one being typed answers to
+    replying_to: Option<String>,
+    /// id of the first message of the conversation the pane is filtered down to
+    pub thread: Option<String>,
+    pub text_buffer: Editor,
+    history: InputHistory,
+    /// reverse search through the history of the current mode, Ctrl-R
+    pub history_search: Option<HistorySearch>,
+    /// suggestions being cycled through with Tab
+    pub completion: Option<Completion>,
+    /// rooms listed by the connection manager
+    pub endpoints: Endpoints,
+    /// rooms heard on the local network, listed by the discovery view
+    pub discovered: Vec<DiscoveredRoom>,
+    pub discovered_selected: usize,
+    // results of the discovery in progress
+    discovery_rx: Option<oneshot::Receiver<io::Result<Vec<DiscoveredRoom>>>>,
+    /// files offered by or to the local user
+    pub transfers: Vec<Transfer>,
+    /// the message search in progress, in Search mode
+    pub search: Option<MessageSearch>,
+    /// set to scroll the pane to the selected message, done by the renderer
+    pub scroll_to_selected: bool,
+    /// where the messages of the room are kept, when enabled
+    log: Option<RoomLog>,
+    // whether the messages of the previous sessions were put back in the pane
+    log_restored: bool,
+    /// links of the messages shown, the newest first, along with their senders
+    pub links: Vec<(String, String)>,
+    pub links_selected: usize,
+    // previews fetched for the messages of the room hosted, by message id
+    previews_tx: mpsc::UnboundedSender<(String, LinkPreview)>,
+    previews_rx: mpsc::UnboundedReceiver<(String, LinkPreview)>,
+    // progress of the files being sent
+    transfer_updates_tx: mpsc::UnboundedSender<TransferUpdate>,
+    transfer_updates_rx: mpsc::UnboundedReceiver<TransferUpdate>,
+    // acknowledgements of the files being sent, by transfer id and receiver
+    uploads: HashMap<(String, String), mpsc::UnboundedSender<u64>>,
+    outgoing_messages_tx: broadcast::Sender<Frame>,
+    incoming_messages_rx: broadcast::Receiver<ServerEvent>,
     // used to send commands to server
     server_commands_tx: broadcast::Sender<ServerCommand>,
     // used to signal to server when renderer_task finishes
     exit_signal_tx: watch::Sender<bool>,
+    // when the last "typing" signal was sent, None once "stopped typing" is sent
+    typing_sent_at: Option<Instant>,
+    last_edit_at: Instant,
+    // remote users currently typing, with the time of their last signal
+    typers: Vec<(String, Instant)>,
+    // whether the room is hosted by the local app
+    hosting: bool,
+    pub connection: ConnectionState,
+    /// link of the room joined or hosted
+    pub room: Option<String>,
+    /// messages received out of sight, and how many of them mention you
+    pub unread: usize,
+    pub unread_mentions: usize,
+    /// whether the terminal has the focus, as reported by the terminal
+    pub focused: bool,
+    // notifications waiting to be sent to the terminal
+    notifications: Vec<String>,
 }
 
 impl Session {
-    pub fn new(server_commands_tx: broadcast::Sender<ServerCommand>) -> Session {
-        let (messages_tx, messages_rx) = broadcast::channel::<Message>(10);
+    pub fn new(
+        server_commands_tx: broadcast::Sender<ServerCommand>,
+        config: Config,
+        keymap: Keymap,
+        theme: Theme,
+    ) -> Session {
+        let (_, messages_rx) = broadcast::channel::<ServerEvent>(CHANNEL_CAPACITY);
+        let (messages_tx, _) = broadcast::channel::<Frame>(CHANNEL_CAPACITY);
+        let (transfer_updates_tx, transfer_updates_rx) = mpsc::unbounded_channel();
+        let (previews_tx, previews_rx) = mpsc::unbounded_channel();
+        let overridden: Vec<Message> = keymap
+            .overridden
+            .iter()
+            .cloned()
+            .map(Message::notice)
+            .collect();
         Session {
+            users: vec![User::root(
+                config.profile.name.clone(),
+                user_color(&config.profile.name, config.profile.color),
+            )],
+            raw: !config.display.markdown,
+            config,
+            keymap,
+            theme,
             input_mode: InputMode::default(),
-            text_buffer: Input::default(),
-            users: vec![User::default()],
-            messages: vec![],
+            text_buffer: Editor::default(),
+            history: InputHistory::load(),
+            history_search: None,
+            completion: None,
+            endpoints: Endpoints::load(),
+            discovered: vec![],
+            discovered_selected: 0,
+            discovery_rx: None,
+            transfers: vec![],
+            transfer_updates_tx,
+            transfer_updates_rx,
+            uploads: HashMap::new(),
+            search: None,
+            scroll_to_selected: false,
+            log: None,
+            log_restored: false,
+            links: vec![],
+            links_selected: 0,
+            previews_tx,
+            previews_rx,
+            messages: overridden,
+            selected: None,
+            scroll_back: 0,
+            code_scroll: 0,
+            raw_users: HashSet::new(),
+            editing: None,
+            replying_to: None,
+            thread: None,
             server_commands_tx,
             incoming_messages_rx: messages_rx,
             outgoing_messages_tx: messages_tx,
             exit_signal_tx: watch::channel(false).0,
+            typing_sent_at: None,
+            last_edit_at: Instant::now(),
+            typers: vec![],
+            hosting: false,
+            connection: ConnectionState::default(),
+            room: None,
+            unread: 0,
+            unread_mentions: 0,
+            focused: true,
+            notifications: vec![],
         }
     }
     pub fn root_user(&self) -> &User {
@@ -41,95 +221,1401 @@ impl Session {
     pub fn nth_user(&self, id: usize) -> &User {
         self.users.get(id).unwrap()
     }
+    pub fn users(&self) -> &[User] {
+        &self.users
+    }
+    pub fn is_hosting(&self) -> bool {
+        self.hosting
+    }
     pub fn switch_mode(&mut self, mode: InputMode) {
+        self.history_search = None;
+        self.completion = None;
+        if let Some(history) = self.history_mut() {
+            history.rewind();
+        }
+        if matches!(self.input_mode, InputMode::Typing) {
+            self.stop_typing();
+            // leaving Typing mode cancels an edit or a reply
+            if self.editing.take().is_some() {
+                self.text_buffer.reset();
+            }
+            self.replying_to = None;
+        }
+        if !matches!(mode, InputMode::Search) {
+            self.search = None;
+        }
         self.input_mode = mode;
     }
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+    /// The message the one being typed answers to
+    pub fn replying_to(&self) -> Option<&Message> {
+        let id = self.replying_to.as_ref()?;
+        self.messages.iter().rev().find(|msg| msg.id == *id)
+    }
+    /// Ids of the messages of the current thread, None when the whole room is shown
+    pub fn thread_ids(&self) -> Option<HashSet<String>> {
+        let root = self.thread.as_ref()?;
+        let mut ids = HashSet::from([root.clone()]);
+        for msg in &self.messages {
+            if msg
+                .reply_to
+                .as_ref()
+                .is_some_and(|parent| ids.contains(parent))
+            {
+                ids.insert(msg.id.clone());
+            }
+        }
+        Some(ids)
+    }
+    /// Ids of the messages shown in the pane: those found by the search or those of the thread,
+    /// None when the whole room is shown
+    pub fn shown_ids(&self) -> Option<HashSet<String>> {
+        match &self.search {
+            Some(search) if !search.query.is_empty() => Some(
+                search
+                    .hits
+                    .iter()
+                    .map(|&i| self.messages[i].id.clone())
+                    .collect(),
+            ),
+            _ => self.thread_ids(),
+        }
+    }
+    /// The message standing out in the pane, the selected search result while searching
+    pub fn focused_msg(&self) -> Option<usize> {
+        match &self.search {
+            Some(search) => search.selected_msg(),
+            None => self.selected,
+        }
+    }
+    /// Opens the Search mode, searching for the query right away if any.
+    /// The messages of the previous sessions are searched too, they are put back in the pane.
+    pub fn open_search(&mut self, query: Option<&str>) -> InputMode {
+        self.restore_log();
+        let mut search = MessageSearch::default();
+        if let Some(query) = query {
+            search.input.set_value(query);
+        }
+        search.refresh(&self.messages);
+        self.search = Some(search);
+        InputMode::Search
+    }
+    /// The editor of the input box, the one of the search query while searching
+    pub fn input(&self) -> &Editor {
+        self.search
+            .as_ref()
+            .map_or(&self.text_buffer, |search| &search.input)
+    }
+    pub fn select_prev_result(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.select_prev();
+        }
+    }
+    pub fn select_next_result(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.select_next();
+        }
+    }
+    pub fn edit_search(&mut self, key: KeyEvent) {
+        if let Some(search) = &mut self.search {
+            if search.input.handle_key(key) {
+                search.refresh(&self.messages);
+            }
+        }
+    }
+    /// Leaves the Search mode with the selected result selected in the pane, scrolled to it
+    pub fn jump_to_result(&mut self) {
+        let Some(i) = self.search.as_ref().and_then(MessageSearch::selected_msg) else {
+            return;
+        };
+        self.selected = Some(i);
+        self.thread = None;
+        self.code_scroll = 0;
+        self.scroll_to_selected = true;
+        self.switch_mode(InputMode::Normal);
+    }
+    /// Puts the logged messages missing from the pane before the others, once per room
+    fn restore_log(&mut self) {
+        let Some(log) = self.log.as_ref().filter(|_| !self.log_restored) else {
+            return;
+        };
+        // an unreadable log leaves the pane as it is
+        let Ok(logged) = log.messages() else { return };
+        self.log_restored = true;
+        let known = self
+            .messages
+            .iter()
+            .map(|msg| msg.id.as_str())
+            .collect::<HashSet<_>>();
+        let terms = self.highlight_terms();
+        let me = &self.root_user().name;
+        let missing = logged
+            .into_iter()
+            .filter(|msg| !known.contains(msg.id.as_str()))
+            .map(|mut msg| {
+                msg.color = readable(msg.color);
+                msg.highlighted = !msg.is_own(me) && !find_terms(&msg.content, &terms).is_empty();
+                msg
+            })
+            .collect::<Vec<_>>();
+        if let Some(selected) = &mut self.selected {
+            *selected += missing.len();
+        }
+        self.messages.splice(0..0, missing);
+    }
+    /// Writes the messages of the room to a file, a new one of the downloads directory when no
+    /// path is given. Returns the message to show.
+    /// The log of the room is exported when it is kept, as `endl-rc export` does, so that the
+    /// messages of previous sessions are included.
+    pub fn export(&self, path: Option<&str>, format: Option<&str>) -> String {
+        let path = path.map(|path| match path.strip_prefix("~/").zip(dirs::home_dir()) {
+            Some((rest, home)) => home.join(rest),
+            None => PathBuf::from(path),
+        });
+        let format = match ExportFormat::resolve(format, path.as_deref()) {
+            Ok(format) => format,
+            Err(e) => return e,
+        };
+        let logged = self.log.as_ref().and_then(|log| log.messages().ok());
+        let messages = match &logged {
+            Some(logged) if !logged.is_empty() => logged,
+            _ => &self.messages,
+        };
+        let count = messages.iter().filter(|msg| !msg.is_notice()).count();
+        if count == 0 {
+            return String::from("No message to export");
+        }
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let dir = self.config.transfers.downloads_dir();
+                if let Err(e) = fs::create_dir_all(&dir) {
+                    return format!("could not create {}: {}", dir.display(), e);
+                }
+                let name = format!(
+                    "endl-rc-{}.{}",
+                    Local::now().format("%Y%m%d-%H%M"),
+                    format.extension()
+                );
+                unique_path(&dir, &name)
+            }
+        };
+        let title = format!("endl-rc · {}", self.room.as_deref().unwrap_or("no room"));
+        match fs::write(&path, transcript(messages, format, &title)) {
+            Ok(()) => format!("Exported {} messages to {}", count, path.display()),
+            Err(e) => format!("could not export to {}: {}", path.display(), e),
+        }
+    }
+    /// Keeps a frame changing the messages in the log of the room
+    fn log_frame(&self, frame: &Frame) {
+        if let Some(log) = &self.log {
+            // losing the log is not worth interrupting the user
+            let _ = log.append(frame);
+        }
+    }
+    /// Indexes of the messages that can be selected in the pane
+    fn selectable_msgs(&self) -> Vec<usize> {
+        let thread = self.thread_ids();
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| {
+                !msg.is_notice() && thread.as_ref().is_none_or(|ids| ids.contains(&msg.id))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+    pub fn select_prev_msg(&mut self) {
+        let before = self.selected.unwrap_or(self.messages.len());
+        if let Some(i) = self.selectable_msgs().into_iter().rfind(|i| *i < before) {
+            self.selected = Some(i);
+            self.code_scroll = 0;
+        }
+    }
+    /// Selects the next message, the selection is dropped past the newest one
+    pub fn select_next_msg(&mut self) {
+        let Some(current) = self.selected else { return };
+        self.selected = self.selectable_msgs().into_iter().find(|i| *i > current);
+        self.code_scroll = 0;
+    }
+    /// Drops the selection, or leaves the thread view when nothing is selected
+    pub fn unselect(&mut self) {
+        self.code_scroll = 0;
+        if self.selected.take().is_none() {
+            self.thread = None;
+        }
+    }
+    /// Scrolls the code blocks of the selected message, the renderer keeps the scroll in bounds
+    pub fn scroll_code(&mut self, right: bool) {
+        if self.selected.is_none() {
+            return;
+        }
+        self.code_scroll = if right {
+            self.code_scroll + CODE_SCROLL_STEP
+        } else {
+            self.code_scroll.saturating_sub(CODE_SCROLL_STEP)
+        };
+    }
+    /// Toggles the raw display of the messages of a user, or of every message.
+    /// Returns the message to show.
+    pub fn toggle_raw(&mut self, user: Option<&str>) -> String {
+        let Some(user) = user else {
+            self.raw = !self.raw;
+            return String::from(if self.raw {
+                "Showing the messages as raw text"
+            } else {
+                "Formatting the messages"
+            });
+        };
+        if self.raw_users.remove(user) {
+            format!("Formatting the messages of {}", user)
+        } else {
+            self.raw_users.insert(user.to_owned());
+            format!("Showing the messages of {} as raw text", user)
+        }
+    }
+    /// Puts the code blocks of the selected message on the clipboard,
+    /// those of the latest message having some when none is selected
+    pub fn copy_code(&mut self) {
+        let msg = match self.selected_msg() {
+            Some(msg) => Some(msg),
+            None => self
+                .messages
+                .iter()
+                .rev()
+                .find(|msg| !msg.deleted && code_blocks(&msg.content).next().is_some()),
+        };
+        let code = msg
+            .filter(|msg| !msg.deleted)
+            .map(|msg| code_blocks(&msg.content).collect::<Vec<_>>().join("\n\n"))
+            .unwrap_or_default();
+        let info = if code.is_empty() {
+            String::from("No code block to copy")
+        } else {
+            match cli_clipboard::set_contents(code) {
+                Ok(()) => String::from("Copied the code block"),
+                Err(e) => format!("Could not copy the code block: {}", e),
+            }
+        };
+        self.switch_mode(InputMode::Info(info));
+    }
+    /// Opens the link picker on the links of the messages shown
+    pub fn pick_link(&mut self) {
+        let thread = self.thread_ids();
+        self.links.clear();
+        for msg in
+            self.messages.iter().rev().filter(|msg| {
+                !msg.deleted && thread.as_ref().is_none_or(|ids| ids.contains(&msg.id))
+            })
+        {
+            for range in find_urls(&msg.content) {
+                let url = &msg.content[range];
+                if !self.links.iter().any(|(link, _)| link == url) {
+                    self.links.push((url.to_owned(), msg.source.clone()));
+                }
+            }
+        }
+        self.links_selected = 0;
+        if self.links.is_empty() {
+            self.switch_mode(InputMode::Info(String::from("No link in the messages")));
+        } else {
+            self.switch_mode(InputMode::Links);
+        }
+    }
+    pub fn select_prev_link(&mut self) {
+        self.links_selected = self.links_selected.saturating_sub(1);
+    }
+    pub fn select_next_link(&mut self) {
+        self.links_selected = (self.links_selected + 1).min(self.links.len().saturating_sub(1));
+    }
+    /// Hands the selected link over to the opener command, without going through a shell
+    pub fn open_selected_link(&mut self) {
+        let Some((url, _)) = self.links.get(self.links_selected) else {
+            return;
+        };
+        let opener = self.config.links.opener();
+        let Some((program, args)) = opener.split_first() else {
+            self.switch_mode(InputMode::Info(String::from("The link opener is empty")));
+            return;
+        };
+        let spawned = std::process::Command::new(program)
+            .args(args)
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                // reap the opener once done, it must not linger as a zombie
+                tokio::task::spawn_blocking(move || child.wait());
+                self.switch_mode(InputMode::Normal);
+            }
+            Err(e) => self.switch_mode(InputMode::Info(format!(
+                "Could not open the link with {}: {}",
+                program, e
+            ))),
+        }
+    }
+    pub fn copy_selected_link(&mut self) {
+        let Some((url, _)) = self.links.get(self.links_selected) else {
+            return;
+        };
+        let info = match cli_clipboard::set_contents(url.clone()) {
+            Ok(()) => String::from("Copied the link"),
+            Err(e) => format!("Could not copy the link: {}", e),
+        };
+        self.switch_mode(InputMode::Info(info));
+    }
+    /// Fetches the previews of the links of a message posted in the room hosted, when enabled
+    fn fetch_previews(&self, msg: &Message) {
+        let config = &self.config.links;
+        if !self.hosting || !config.previews {
+            return;
+        }
+        let mut seen = HashSet::new();
+        let urls = find_urls(&msg.content)
+            .into_iter()
+            .map(|range| &msg.content[range])
+            .filter(|url| is_allowed(url, &config.preview_domains) && seen.insert(*url));
+        for url in urls.take(MAX_PREVIEWS) {
+            let (id, url, proxy) = (msg.id.clone(), url.to_owned(), config.proxy.clone());
+            let previews_tx = self.previews_tx.clone();
+            tokio::spawn(async move {
+                // a link without preview is simply shown as is
+                if let Ok(preview) = fetch_preview(url, proxy).await {
+                    let _ = previews_tx.send((id, preview));
+                }
+            });
+        }
+    }
+    /// Attaches a preview to its message, the host also sends it to the room
+    fn apply_preview(&mut self, id: &str, preview: LinkPreview) {
+        let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == id) else {
+            return;
+        };
+        if msg.deleted || msg.previews.iter().any(|known| known.url == preview.url) {
+            return;
+        }
+        msg.previews.push(preview.clone());
+        if self.hosting {
+            let _ = self.outgoing_messages_tx.send(Frame::LinkPreview {
+                id: id.to_owned(),
+                preview,
+            });
+        }
+    }
+    /// Starts typing an answer to the selected message
+    pub fn reply_to_selected_msg(&mut self) {
+        if let Some(msg) = self.selected_msg().filter(|msg| !msg.deleted) {
+            let id = msg.id.clone();
+            self.switch_mode(InputMode::Typing);
+            self.replying_to = Some(id);
+        }
+    }
+    /// Toggles the reaction of the root user to the selected message
+    pub fn react_to_selected_msg(&mut self, emoji: &str) {
+        let name = self.root_user().name.clone();
+        let Some(msg) = self.selected_msg().filter(|msg| !msg.deleted) else {
+            return;
+        };
+        let (id, add) = (msg.id.clone(), !msg.has_reacted(emoji, &name));
+        let frame = Frame::React {
+            id: id.clone(),
+            emoji: emoji.to_owned(),
+            name: name.clone(),
+            add,
+        };
+        self.log_frame(&frame);
+        let _ = self.outgoing_messages_tx.send(frame);
+        self.apply_reaction(&id, emoji, &name, add);
+    }
+    fn apply_reaction(&mut self, id: &str, emoji: &str, name: &str, add: bool) {
+        if let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == id) {
+            msg.set_reaction(emoji, name, add);
+        }
+    }
+    /// Filters the pane down to the conversation of the selected message, or shows the whole room again
+    pub fn toggle_thread(&mut self) {
+        if self.thread.take().is_some() {
+            return;
+        }
+        let Some(mut root) = self.selected_msg() else {
+            return;
+        };
+        // walk up to the message that started the conversation
+        while let Some(parent) = root
+            .reply_to
+            .as_ref()
+            .and_then(|parent| self.messages.iter().find(|msg| msg.id == *parent))
+        {
+            root = parent;
+        }
+        self.thread = Some(root.id.clone());
+    }
+    pub fn selected_msg(&self) -> Option<&Message> {
+        self.selected.and_then(|i| self.messages.get(i))
+    }
+    /// The selected message if it can be modified by the root user
+    fn selected_own_msg(&mut self) -> Option<&Message> {
+        match self.selected_msg() {
+            Some(msg) if msg.is_own(&self.root_user().name) && !msg.deleted => self.selected_msg(),
+            Some(_) => {
+                self.switch_mode(InputMode::Info(String::from(
+                    "Only your own messages can be modified",
+                )));
+                None
+            }
+            None => None,
+        }
+    }
+    /// Loads the selected message in the text box to be edited
+    pub fn edit_selected_msg(&mut self) {
+        if let Some(msg) = self.selected_own_msg() {
+            let (id, content) = (msg.id.clone(), msg.content.clone());
+            self.text_buffer.set_value(&content);
+            self.switch_mode(InputMode::Typing);
+            self.editing = Some(id);
+        }
+    }
+    /// Asks the host to delete the selected message, it is deleted once the host accepts it
+    pub fn delete_selected_msg(&mut self) {
+        if let Some(msg) = self.selected_own_msg() {
+            let delete = Frame::Delete { id: msg.id.clone() };
+            self.modify(delete);
+        }
+    }
+    /// Sends an edit or a deletion, which the host hands back once accepted
+    fn modify(&mut self, frame: Frame) {
+        if self.outgoing_messages_tx.send(frame).is_err() {
+            self.messages.push(Message::notice(String::from(
+                "messages can only be modified in the room they were sent to",
+            )));
+        }
+    }
+    fn apply_edit(&mut self, id: &str, content: String, suspicious: bool) {
+        let terms = self.highlight_terms();
+        let me = self.root_user().name.clone();
+        if let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == id) {
+            if !msg.is_own(&me) {
+                msg.highlighted = !find_terms(&content, &terms).is_empty();
+            }
+            msg.content = content;
+            msg.edited = true;
+            msg.suspicious |= suspicious;
+        }
+    }
+    fn apply_delete(&mut self, id: &str) {
+        if let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == id) {
+            msg.content.clear();
+            msg.deleted = true;
+        }
+    }
+    /// The history of the current mode, only Typing and Command modes have one
+    fn history_mut(&mut self) -> Option<&mut History> {
+        match self.input_mode {
+            InputMode::Typing => Some(&mut self.history.typing),
+            InputMode::Command => Some(&mut self.history.command),
+            _ => None,
+        }
+    }
+    /// Adds the input to the history of the current mode and saves it
+    fn remember_input(&mut self) {
+        let input = self.text_buffer.value();
+        if let Some(history) = self.history_mut() {
+            history.push(input);
+            // losing the history is not worth interrupting the user
+            let _ = self.history.save();
+        }
+    }
+    /// Replaces the input with the previous entry of the history, Up
+    pub fn history_prev(&mut self) {
+        let current = self.text_buffer.value();
+        if let Some(entry) = self
+            .history_mut()
+            .and_then(|history| history.prev(&current))
+            .map(str::to_owned)
+        {
+            self.text_buffer.set_value(&entry);
+        }
+    }
+    /// Replaces the input with the next entry of the history, Down
+    pub fn history_next(&mut self) {
+        if let Some(entry) = self
+            .history_mut()
+            .and_then(|history| history.next())
+            .map(str::to_owned)
+        {
+            self.text_buffer.set_value(&entry);
+        }
+    }
+    /// Starts a reverse search, or looks for an older match when already searching
+    pub fn search_history(&mut self) {
+        let search = self.history_search.take().unwrap_or_else(|| HistorySearch {
+            original: self.text_buffer.value(),
+            ..HistorySearch::default()
+        });
+        let before = search.found.unwrap_or(usize::MAX);
+        self.run_history_search(search, before);
+    }
+    /// Updates the query of the reverse search, looking for a match from the newest entry
+    pub fn edit_history_query(&mut self, edit: impl FnOnce(&mut String)) {
+        if let Some(mut search) = self.history_search.take() {
+            edit(&mut search.query);
+            self.run_history_search(search, usize::MAX);
+        }
+    }
+    fn run_history_search(&mut self, mut search: HistorySearch, before: usize) {
+        let Some(history) = self.history_mut() else {
+            return;
+        };
+        // an empty query or no older match keeps the current result
+        let found = history
+            .search(&search.query, before)
+            .filter(|_| !search.query.is_empty());
+        search.failing = found.is_none() && !search.query.is_empty();
+        if let Some(found) = found {
+            search.found = Some(found);
+            let entry = history.get(found).unwrap_or_default().to_owned();
+            self.text_buffer.set_value(&entry);
+        }
+        self.history_search = Some(search);
+    }
+    /// Ends the reverse search, keeping the match or restoring the input
+    pub fn end_history_search(&mut self, accept: bool) {
+        if let Some(search) = self.history_search.take() {
+            if !accept {
+                self.text_buffer.set_value(&search.original);
+            }
+        }
+    }
+    /// Completes the word before the cursor, or cycles through the suggestions, Tab and Shift-Tab
+    pub fn complete(&mut self, forward: bool) {
+        if let Some(completion) = self.completion.as_mut() {
+            completion.cycle(forward);
+        } else {
+            self.completion = self.completion_candidates();
+        }
+        if let Some(completion) = &self.completion {
+            let candidate = completion.current().to_owned();
+            self.text_buffer.replace_word_before_cursor(&candidate);
+        }
+    }
+    /// Suggestions for the word before the cursor, depending on the mode and the words before it
+    fn completion_candidates(&self) -> Option<Completion> {
+        let word = self.text_buffer.word_before_cursor();
+        let names = self.users.iter().skip(1).map(|user| user.name.as_str());
+        match self.input_mode {
+            InputMode::Typing => {
+                let name = word.strip_prefix('@')?;
+                let mentions = names.map(|name| format!("@{}", name)).collect::<Vec<_>>();
+                Completion::new(&format!("@{}", name), mentions.iter().map(String::as_str))
+            }
+            InputMode::Command => {
+                let (_, line) = self.text_buffer.cursor();
+                let previous = line.split_whitespace().count() - usize::from(!word.is_empty());
+                if previous == 0 {
+                    return Completion::new(word, COMMANDS.iter().map(|command| command.name));
+                }
+                let words = line.split_whitespace().take(previous).collect::<Vec<_>>();
+                let command = find_command(words[0])?;
+                match command.arg_after(&words[1..]).map(|arg| arg.kind) {
+                    Some(ArgKind::JoinTarget) => {
+                        let targets = self.history.command.recent().filter_map(|entry| {
+                            let mut words = entry.split_whitespace();
+                            let command = words.next().and_then(find_command)?;
+                            words.next().filter(|_| command.name == "join")
+                        });
+                        Completion::new(word, targets)
+                    }
+                    Some(ArgKind::Text) | Some(ArgKind::Flag) => None,
+                    Some(ArgKind::ExportFormat) => {
+                        Completion::new(word, ["txt", "md", "json", "html"])
+                    }
+                    Some(ArgKind::Theme) => {
+                        let themes = Theme::available();
+                        Completion::new(word, themes.iter().map(String::as_str))
+                    }
+                    Some(ArgKind::Path) => {
+                        let paths = path_candidates(word);
+                        Completion::new(word, paths.iter().map(String::as_str))
+                    }
+                    Some(ArgKind::Offer) => {
+                        let offers = self.pending_offers().collect::<Vec<_>>();
+                        Completion::new(word, offers)
+                    }
+                    Some(ArgKind::Reaction) => {
+                        let codes = shortcodes().collect::<Vec<_>>();
+                        Completion::new(word, codes.iter().map(String::as_str))
+                    }
+                    Some(ArgKind::User) | None => Completion::new(word, names),
+                }
+            }
+            _ => None,
+        }
+    }
+    /// Inserts pasted text in the input box, commands are kept on a single line
+    pub fn paste(&mut self, text: &str) {
+        match self.input_mode {
+            InputMode::Typing => {
+                self.text_buffer.insert_str(text);
+                self.on_text_edited();
+            }
+            InputMode::Command => self
+                .text_buffer
+                .insert_str(&text.replace(['\r', '\n'], " ")),
+            _ => {}
+        }
+    }
+    /// Names of the remote users currently typing
+    pub fn typers(&self) -> impl Iterator<Item = &str> {
+        self.typers.iter().map(|(name, _)| name.as_str())
+    }
+    /// Called whenever the message being typed changes, signals the room without flooding it
+    pub fn on_text_edited(&mut self) {
+        self.last_edit_at = Instant::now();
+        if self.text_buffer.is_empty() {
+            self.stop_typing();
+        } else if self.config.profile.typing_indicators
+            && self
+                .typing_sent_at
+                .is_none_or(|sent_at| sent_at.elapsed() >= TYPING_THROTTLE)
+        {
+            self.typing_sent_at = Some(Instant::now());
+            self.send_typing(true);
+        }
+    }
+    fn stop_typing(&mut self) {
+        if self.typing_sent_at.take().is_some() {
+            self.send_typing(false);
+        }
+    }
+    fn send_typing(&self, typing: bool) {
+        let _ = self.outgoing_messages_tx.send(Frame::Typing {
+            name: self.root_user().name.clone(),
+            typing,
+        });
+    }
+    /// "@name" along with the configured highlight words
+    pub fn highlight_terms(&self) -> Vec<String> {
+        let mut terms = vec![format!("@{}", self.root_user().name)];
+        terms.extend(self.config.notifications.highlight_words.iter().cloned());
+        terms
+    }
+    /// Notifications to be sent to the terminal, emptied once taken
+    pub fn take_notifications(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.notifications)
+    }
+    /// Housekeeping for time based state, called on every render
+    pub fn tick(&mut self) {
+        if self.last_edit_at.elapsed() >= TYPING_IDLE {
+            self.stop_typing();
+        }
+        self.typers
+            .retain(|(_, signaled_at)| signaled_at.elapsed() < TYPING_EXPIRY);
+        if let Some(discovery_rx) = &mut self.discovery_rx {
+            match discovery_rx.try_recv() {
+                Err(TryRecvError::Empty) => {}
+                Ok(Ok(rooms)) => {
+                    self.discovery_rx = None;
+                    self.discovered = rooms;
+                }
+                Ok(Err(e)) => {
+                    self.discovery_rx = None;
+                    self.switch_mode(InputMode::Info(format!("could not discover rooms: {}", e)));
+                }
+                Err(TryRecvError::Closed) => self.discovery_rx = None,
+            }
+        }
+        while let Ok(update) = self.transfer_updates_rx.try_recv() {
+            self.on_transfer_update(update);
+        }
+        while let Ok((id, preview)) = self.previews_rx.try_recv() {
+            self.apply_preview(&id, preview);
+        }
+        // the newest messages are in sight again
+        if self.focused && self.scroll_back == 0 && self.thread.is_none() {
+            self.unread = 0;
+            self.unread_mentions = 0;
+        }
+        for msg in self.messages.iter_mut() {
+            if matches!(msg.status, Some(DeliveryStatus::Pending(since)) if since.elapsed() >= ACK_TIMEOUT)
+            {
+                msg.status = Some(DeliveryStatus::Failed);
+            }
+        }
+    }
     pub async fn send_user_msg(&mut self) {
-        let msg = Message::new(
-            format!("{}\n", self.text_buffer.value()),
-            crate::views::renderer::COLOR_TRON,
-            String::from("User"),
+        if self.text_buffer.is_empty() {
+            return;
+        }
+        // the host would drop the link rather than relay it
+        let probe = Message::new(
+            self.text_buffer.value(),
+            self.root_user().color,
+            self.root_user().name.clone(),
         );
-        if self.outgoing_messages_tx.send(msg.clone()).is_ok() {
-            self.messages.push(msg);
-            // empty the text input field
+        if !Frame::Chat(probe).fits() {
+            self.messages.push(Message::notice(String::from(
+                "the message is too long to be sent",
+            )));
+            return;
+        }
+        if let Some(id) = self.editing.take() {
+            let edit = Frame::Edit {
+                id,
+                content: self.text_buffer.value(),
+                suspicious: false,
+            };
+            self.modify(edit);
             self.text_buffer.reset();
+            self.switch_mode(InputMode::Normal);
+            return;
+        }
+        self.remember_input();
+        let mut msg = Message::new(
+            self.text_buffer.value(),
+            self.root_user().color,
+            self.root_user().name.clone(),
+        );
+        msg.reply_to = self.replying_to.take();
+        msg.status = Some(self.deliver(&msg));
+        self.log_frame(&Frame::Chat(msg.clone()));
+        self.fetch_previews(&msg);
+        self.messages.push(msg);
+        // empty the text input field
+        self.text_buffer.reset();
+        self.stop_typing();
+    }
+    /// Sends again the messages that could not be delivered
+    pub fn retry_failed_msgs(&mut self) {
+        let failed = self
+            .messages
+            .iter()
+            .filter(|msg| msg.status == Some(DeliveryStatus::Failed))
+            .cloned()
+            .collect::<Vec<_>>();
+        for msg in failed {
+            let status = self.deliver(&msg);
+            if let Some(sent) = self.messages.iter_mut().find(|sent| sent.id == msg.id) {
+                sent.status = Some(status);
+            }
+        }
+    }
+    /// Hands a message over to the server, the status it should be in afterwards is returned
+    fn deliver(&self, msg: &Message) -> DeliveryStatus {
+        match self.outgoing_messages_tx.send(Frame::Chat(msg.clone())) {
+            // no link is listening, nobody is connected
+            Err(_) => DeliveryStatus::Failed,
+            // the host acknowledges the messages once they reached a peer, its own ones included
+            Ok(_) => DeliveryStatus::Pending(Instant::now()),
         }
     }
     pub async fn listen_for_msgs(&mut self) {
-        if let Ok(msg) = self.incoming_messages_rx.recv().await {
-            self.messages.push(msg);
+        match self.incoming_messages_rx.recv().await {
+            Ok(event) => self.handle_server_event(event),
+            Err(RecvError::Lagged(_)) => {}
+            // no room is open, wait for the renderer to be interrupted by a key
+            Err(RecvError::Closed) => std::future::pending().await,
+        }
+        // handle the whole burst before rendering, file chunks come in quick succession
+        while let Ok(event) = self.incoming_messages_rx.try_recv() {
+            self.handle_server_event(event);
         }
     }
-    pub fn execute_cmd(&mut self) -> Result<InputMode, ()> {
-        let info: String;
-        match self.parse_cmd(&mut self.text_buffer.value().to_owned()) {
-            Command::Invite => {
-                // TODO: figure out how to pass link from server
-                // cli_clipboard::set_contents(self.server.get_invite_link()).unwrap();
-                // info = String::from("Invite Link copied to clipboard");
-                info = String::from("Not yet implemented!");
-            }
-            Command::Join(link) => {
-                let (exit_signal_tx, exit_signal_rx) = watch::channel::<bool>(false);
-                self.exit_signal_tx = exit_signal_tx;
-                let (incoming_messages_tx, incoming_messages_rx) =
-                    broadcast::channel::<Message>(10);
-                let (outgoing_messages_tx, _) = broadcast::channel::<Message>(10);
-
-                self.incoming_messages_rx = incoming_messages_rx;
-                self.outgoing_messages_tx = outgoing_messages_tx.clone();
-                let _ = self.server_commands_tx.send(ServerCommand::JoinRoom((
-                    link.clone(),
-                    exit_signal_rx,
-                    incoming_messages_tx,
-                    outgoing_messages_tx,
-                )));
-                info = format!("joined room {}", link);
+    fn handle_server_event(&mut self, event: ServerEvent) {
+        match event {
+            ServerEvent::Frame(Frame::Chat(mut msg)) => {
+                msg.color = readable(msg.color);
+                msg.highlighted = !find_terms(&msg.content, &self.highlight_terms()).is_empty();
+                // the newest messages are out of sight
+                if !self.focused || self.scroll_back > 0 || self.thread.is_some() {
+                    self.unread += 1;
+                    if msg.highlighted {
+                        self.unread_mentions += 1;
+                        self.notifications
+                            .push(format!("{}: {}", msg.source, msg.snippet(100)));
+                    }
+                }
+                self.typers.retain(|(name, _)| *name != msg.source);
+                self.log_frame(&Frame::Chat(msg.clone()));
+                self.fetch_previews(&msg);
+                self.messages.push(msg);
             }
-            Command::Unknown => {
-                info = String::from("Unknown Command!");
+            ServerEvent::Frame(
+                ref frame @ Frame::Edit {
+                    ref id,
+                    ref content,
+                    suspicious,
+                },
+            ) => {
+                self.log_frame(frame);
+                self.apply_edit(id, content.clone(), suspicious);
             }
-            Command::Quit => {
-                return Err(());
+            ServerEvent::Frame(ref frame @ Frame::Delete { ref id }) => {
+                self.log_frame(frame);
+                self.apply_delete(id);
             }
-            Command::Run => {
-                let (exit_signal_tx, exit_signal_rx) = watch::channel::<bool>(false);
-                self.exit_signal_tx = exit_signal_tx;
-                let (incoming_messages_tx, incoming_messages_rx) =
-                    broadcast::channel::<Message>(10);
-                let (outgoing_messages_tx, _) = broadcast::channel::<Message>(10);
-
-                self.incoming_messages_rx = incoming_messages_rx;
-                self.outgoing_messages_tx = outgoing_messages_tx.clone();
-                let _ = self.server_commands_tx.send(ServerCommand::HostRoom((
-                    exit_signal_rx,
-                    incoming_messages_tx,
-                    outgoing_messages_tx,
-                )));
-                info = String::from("Server running on localhost:8080");
+            ServerEvent::Frame(Frame::LinkPreview { id, preview }) => {
+                self.apply_preview(&id, preview)
+            }
+            ServerEvent::Frame(
+                ref frame @ Frame::React {
+                    ref id,
+                    ref emoji,
+                    ref name,
+                    add,
+                },
+            ) => {
+                self.log_frame(frame);
+                self.apply_reaction(id, emoji, name, add);
+            }
+            ServerEvent::Frame(ref frame @ Frame::Ack { ref id, timestamp }) => {
+                if let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == *id) {
+                    msg.status = Some(DeliveryStatus::Delivered);
+                    if let Some(timestamp) = timestamp {
+                        msg.timestamp = timestamp;
+                        self.log_frame(frame);
+                    }
+                }
+            }
+            ServerEvent::Frame(Frame::Typing { name, typing }) => {
+                self.typers.retain(|(typer, _)| *typer != name);
+                if typing {
+                    self.typers.push((name, Instant::now()));
+                }
+            }
+            ServerEvent::Frame(Frame::FileOffer {
+                id,
+                name,
+                to,
+                file_name,
+                size,
+            }) => {
+                let me = &self.root_user().name;
+                if name != *me && to.as_ref().is_none_or(|to| to == me) {
+                    self.on_file_offer(id, name, &file_name, size);
+                }
+            }
+            ServerEvent::Frame(Frame::FileAnswer {
+                id,
+                name,
+                to,
+                accept,
+            }) => {
+                if to == self.root_user().name {
+                    self.on_file_answer(&id, name, accept);
+                }
+            }
+            ServerEvent::Frame(Frame::FileAck {
+                id,
+                name,
+                to,
+                received,
+            }) => {
+                if to == self.root_user().name {
+                    if let Some(acks_tx) = self.uploads.get(&(id, name)) {
+                        let _ = acks_tx.send(received);
+                    }
+                }
+            }
+            ServerEvent::Frame(Frame::FileChunk {
+                id,
+                name,
+                to,
+                offset,
+                data,
+                checksum,
+            }) => {
+                if to == self.root_user().name {
+                    self.on_file_chunk(&id, &name, offset, &data, checksum);
+                }
+            }
+            // link level frames are consumed by the server
+            ServerEvent::Frame(
+                Frame::Ping { .. }
+                | Frame::Pong { .. }
+                | Frame::Hello { .. }
+                | Frame::Leave { .. }
+                | Frame::Refused { id: None, .. },
+            ) => {}
+            ServerEvent::Frame(Frame::Refused {
+                reason,
+                id: Some(id),
+            }) => {
+                if let Some(msg) = self.messages.iter_mut().rev().find(|msg| msg.id == id) {
+                    // a message of the root user the host did not relay
+                    if msg.status.is_some() {
+                        msg.status = Some(DeliveryStatus::Failed);
+                    }
+                }
+                self.messages.push(Message::notice(reason));
+            }
+            ServerEvent::Connected(link) => {
+                self.connection = ConnectionState::Connected;
+                if self.config.history.log_messages {
+                    self.log = Some(RoomLog::of_room(&link));
+                }
+                if !self.hosting {
+                    self.endpoints.seen(&link);
+                    // losing the recent rooms is not worth interrupting the user
+                    let _ = self.endpoints.save();
+                }
+                self.room = Some(link);
+            }
+            ServerEvent::PeerJoined(peer, color) => {
+                let id = self.users.iter().map(|user| user.id).max().unwrap_or(0) + 1;
+                self.messages
+                    .push(Message::notice(format!("{} joined the room", peer)));
+                self.users.push(User::new(id, peer, readable(color)));
+            }
+            ServerEvent::PeerLeft(peer) => {
+                self.messages
+                    .push(Message::notice(format!("{} left the room", peer)));
+                // never remove the root user
+                if let Some(pos) = self.users.iter().skip(1).position(|u| u.name == peer) {
+                    self.users.remove(pos + 1);
+                }
+            }
+            ServerEvent::PeerLatency(peer, rtt) => {
+                if let Some(user) = self.users.iter_mut().skip(1).find(|u| u.name == peer) {
+                    user.latency = Some(rtt);
+                }
+            }
+            ServerEvent::Reconnecting(attempt) => {
+                // the roster is sent again by the host once reconnected
+                self.users.truncate(1);
+                self.connection = ConnectionState::Reconnecting(attempt);
+                self.messages.push(Message::notice(format!(
+                    "connection lost, reconnecting (attempt {})",
+                    attempt
+                )))
+            }
+            ServerEvent::Disconnected(reason) => {
+                self.users.truncate(1);
+                self.connection = ConnectionState::Disconnected;
+                self.messages.push(Message::notice(reason))
             }
         }
-        self.text_buffer.reset();
-        Ok(InputMode::Info(info))
     }
+    /// Drops the current room (if any) and creates the channels for a new one
+    fn open_room(
+        &mut self,
+    ) -> (
+        watch::Receiver<bool>,
+        broadcast::Sender<ServerEvent>,
+        broadcast::Sender<Frame>,
+    ) {
+        let (exit_signal_tx, exit_signal_rx) = watch::channel::<bool>(false);
+        self.exit_signal_tx = exit_signal_tx;
+        let (incoming_messages_tx, incoming_messages_rx) =
+            broadcast::channel::<ServerEvent>(CHANNEL_CAPACITY);
+        let (outgoing_messages_tx, _) = broadcast::channel::<Frame>(CHANNEL_CAPACITY);
 
-    fn verify_join_link(&self, link: String) -> Option<String> {
-        // TODO: implement
-        Some(link)
+        self.incoming_messages_rx = incoming_messages_rx;
+        self.outgoing_messages_tx = outgoing_messages_tx.clone();
+        self.users.truncate(1);
+        self.typers.clear();
+        self.log = None;
+        self.log_restored = false;
+        self.connection = ConnectionState::Connecting;
+        (exit_signal_rx, incoming_messages_tx, outgoing_messages_tx)
     }
-
-    fn parse_cmd(&self, cmd: &mut str) -> Command {
-        let words: Vec<&str> = cmd.split_whitespace().collect();
-        return match words.first() {
-            Some(&"quit") => Command::Quit,
-            Some(&"inv") => Command::Invite,
-            Some(&"run") => Command::Run,
-            Some(&"join") if words.len() == 2 => self
-                .verify_join_link(words[1].to_string())
-                .map(Command::Join)
-                .unwrap_or(Command::Unknown),
-            _ => Command::Unknown,
+    /// Joins the room behind the link, returns the message to show
+    pub fn join_room(&mut self, link: &str) -> String {
+        let (exit_signal_rx, incoming_messages_tx, outgoing_messages_tx) = self.open_room();
+        self.hosting = false;
+        let _ = self.server_commands_tx.send(ServerCommand::JoinRoom((
+            link.to_owned(),
+            exit_signal_rx,
+            incoming_messages_tx,
+            outgoing_messages_tx,
+        )));
+        format!("joined room {}", link)
+    }
+    /// Starts hosting a room, returns the message to show
+    pub fn host_room(&mut self, announce: bool) -> String {
+        let (exit_signal_rx, incoming_messages_tx, outgoing_messages_tx) = self.open_room();
+        self.hosting = true;
+        let announced = announce || self.config.network.announce;
+        let _ = self.server_commands_tx.send(ServerCommand::HostRoom((
+            announced,
+            exit_signal_rx,
+            incoming_messages_tx,
+            outgoing_messages_tx,
+        )));
+        format!(
+            "Server running on {}",
+            self.config.network.bind_address(announced)
+        )
+    }
+    pub fn is_discovering(&self) -> bool {
+        self.discovery_rx.is_some()
+    }
+    /// Starts listening for the rooms announced on the local network, returns the mode to switch to
+    pub fn discover_rooms(&mut self) -> InputMode {
+        let (results_tx, results_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = results_tx.send(discover(DISCOVERY_TIME).await);
+        });
+        self.discovery_rx = Some(results_rx);
+        self.discovered.clear();
+        self.discovered_selected = 0;
+        InputMode::Discovery
+    }
+    pub fn select_prev_discovered(&mut self) {
+        self.discovered_selected = self.discovered_selected.saturating_sub(1);
+    }
+    pub fn select_next_discovered(&mut self) {
+        self.discovered_selected =
+            (self.discovered_selected + 1).min(self.discovered.len().saturating_sub(1));
+    }
+    /// Joins the room selected in the discovery view
+    pub fn connect_discovered_room(&mut self) {
+        if let Some(link) = self
+            .discovered
+            .get(self.discovered_selected)
+            .map(|room| room.link.clone())
+        {
+            let info = self.join_room(&link);
+            self.switch_mode(InputMode::Info(info));
+        }
+    }
+    /// Opens the Command mode on a "save" command for the room selected in the discovery view
+    pub fn save_discovered_room(&mut self) {
+        if let Some(room) = self.discovered.get(self.discovered_selected) {
+            let command = format!("save {} ", room.link);
+            self.switch_mode(InputMode::Command);
+            self.text_buffer.set_value(&command);
+        }
+    }
+    /// Offers a file to the room, or to a single user, returns the mode to switch to
+    pub fn send_file(&mut self, path: &str, to: Option<&str>) -> InputMode {
+        let path = match path.strip_prefix("~/").zip(dirs::home_dir()) {
+            Some((rest, home)) => home.join(rest),
+            None => PathBuf::from(path),
+        };
+        let size = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            Ok(_) => return InputMode::Info(format!("{} is not a file", path.display())),
+            Err(e) => return InputMode::Info(format!("cannot send {}: {}", path.display(), e)),
+        };
+        if size > self.config.transfers.max_size() {
+            return InputMode::Info(format!(
+                "{} is larger than the {} MB limit",
+                path.display(),
+                self.config.transfers.max_size_mb
+            ));
+        }
+        if to.is_some_and(|to| !self.users.iter().skip(1).any(|user| user.name == to)) {
+            return InputMode::Info(format!("{} is not in the room", to.unwrap_or_default()));
+        }
+        let transfer = Transfer::outgoing(random_id(), path, size, to.map(String::from));
+        let offer = Frame::FileOffer {
+            id: transfer.id.clone(),
+            name: self.root_user().name.clone(),
+            to: transfer.peer.clone(),
+            file_name: transfer.file_name.clone(),
+            size,
+        };
+        if self.outgoing_messages_tx.send(offer).is_err() {
+            return InputMode::Info(String::from("Join or host a room to send files"));
+        }
+        self.messages.push(Message::notice(format!(
+            "offered {} ({}) to {}",
+            transfer.file_name,
+            format_size(size),
+            to.unwrap_or("the room")
+        )));
+        self.transfers.push(transfer);
+        InputMode::Normal
+    }
+    /// Accepts or rejects the offer of the file, the latest pending one when no name is given.
+    /// Returns the mode to switch to.
+    pub fn answer_offer(&mut self, file_name: Option<&str>, accept: bool) -> InputMode {
+        let Some(transfer) = self.transfers.iter_mut().rev().find(|transfer| {
+            transfer.direction == Direction::Incoming
+                && transfer.state == TransferState::Offered
+                && file_name.is_none_or(|name| transfer.file_name == name)
+        }) else {
+            return InputMode::Info(String::from("No file is waiting to be accepted"));
+        };
+        let sender = transfer.peer.clone().unwrap_or_default();
+        let notice = if !accept {
+            transfer.state = TransferState::Rejected;
+            format!("declined {} from {}", transfer.file_name, sender)
+        } else {
+            let downloads = self.config.transfers.downloads_dir();
+            // an empty file is complete right away, no chunk will come
+            match transfer
+                .start_download(&downloads)
+                .map(|_| transfer.size > 0 || transfer.write_chunk(0, &[]).is_ok())
+            {
+                Ok(_) => format!("receiving {} from {}", transfer.file_name, sender),
+                Err(e) => {
+                    transfer.fail();
+                    return InputMode::Info(format!(
+                        "cannot save to {}: {}",
+                        downloads.display(),
+                        e
+                    ));
+                }
+            }
+        };
+        let answer = Frame::FileAnswer {
+            id: transfer.id.clone(),
+            name: self.users[0].name.clone(),
+            to: sender,
+            accept,
+        };
+        let _ = self.outgoing_messages_tx.send(answer);
+        self.messages.push(Message::notice(notice));
+        InputMode::Normal
+    }
+    /// Names of the files waiting to be accepted, the latest first
+    pub fn pending_offers(&self) -> impl Iterator<Item = &str> {
+        self.transfers
+            .iter()
+            .rev()
+            .filter(|transfer| {
+                transfer.direction == Direction::Incoming
+                    && transfer.state == TransferState::Offered
+            })
+            .map(|transfer| transfer.file_name.as_str())
+    }
+    fn on_file_offer(&mut self, id: String, sender: String, file_name: &str, size: u64) {
+        let transfer = Transfer::incoming(id, sender.clone(), file_name, size);
+        if size > self.config.transfers.max_size() {
+            let _ = self.outgoing_messages_tx.send(Frame::FileAnswer {
+                id: transfer.id.clone(),
+                name: self.root_user().name.clone(),
+                to: sender.clone(),
+                accept: false,
+            });
+            self.messages.push(Message::notice(format!(
+                "declined {} ({}) from {}, larger than the {} MB limit",
+                transfer.file_name,
+                format_size(size),
+                sender,
+                self.config.transfers.max_size_mb
+            )));
+            return;
+        }
+        self.messages.push(Message::notice(format!(
+            "{} offers {} ({}), enter \"accept\" or \"reject\"",
+            sender,
+            transfer.file_name,
+            format_size(size)
+        )));
+        self.transfers.push(transfer);
+    }
+    fn on_file_answer(&mut self, id: &str, peer: String, accept: bool) {
+        let Some(offer) = self.transfers.iter().find(|transfer| {
+            transfer.id == id
+                && transfer.direction == Direction::Outgoing
+                && transfer.peer.as_ref().is_none_or(|to| *to == peer)
+        }) else {
+            return;
+        };
+        if !accept {
+            self.messages.push(Message::notice(format!(
+                "{} declined {}",
+                peer, offer.file_name
+            )));
+            return;
+        }
+        // one transfer per receiver, the offer itself when it was made to a single user
+        let upload = Upload {
+            id: id.to_owned(),
+            path: offer.path.clone(),
+            size: offer.size,
+            name: self.root_user().name.clone(),
+            peer: peer.clone(),
+        };
+        let mut transfer = Transfer::outgoing(
+            id.to_owned(),
+            offer.path.clone(),
+            offer.size,
+            Some(peer.clone()),
+        );
+        transfer.state = TransferState::Active(0);
+        match self
+            .transfers
+            .iter_mut()
+            .find(|transfer| transfer.id == id && transfer.peer.as_deref() == Some(peer.as_str()))
+        {
+            Some(offer) => offer.state = TransferState::Active(0),
+            None => self.transfers.push(transfer),
+        }
+        let (acks_tx, acks_rx) = mpsc::unbounded_channel();
+        self.uploads.insert((id.to_owned(), peer), acks_tx);
+        tokio::spawn(send_file(
+            upload,
+            self.outgoing_messages_tx.clone(),
+            self.transfer_updates_tx.clone(),
+            acks_rx,
+        ));
+    }
+    fn on_file_chunk(&mut self, id: &str, sender: &str, offset: u64, data: &str, checksum: u64) {
+        let me = self.root_user().name.clone();
+        let Some(transfer) = self.transfers.iter_mut().find(|transfer| {
+            transfer.id == id
+                && transfer.direction == Direction::Incoming
+                && transfer.peer.as_deref() == Some(sender)
+                && matches!(transfer.state, TransferState::Active(_))
+        }) else {
+            return;
+        };
+        // a damaged chunk is not acknowledged, the sender sends it again
+        let Some(bytes) = decode_base64(data).filter(|bytes| transfer_checksum(bytes) == checksum)
+        else {
+            return;
+        };
+        let written = transfer.write_chunk(offset, &bytes);
+        let received = match transfer.state {
+            TransferState::Active(received) => Some(received),
+            TransferState::Done => Some(transfer.size),
+            _ => None,
         };
+        if let Some(received) = received {
+            let _ = self.outgoing_messages_tx.send(Frame::FileAck {
+                id: id.to_owned(),
+                name: me,
+                to: sender.to_owned(),
+                received,
+            });
+        }
+        let notice = match written {
+            Ok(false) => return,
+            Ok(true) => format!(
+                "saved {} from {} to {}",
+                transfer.file_name,
+                sender,
+                transfer.path.display()
+            ),
+            Err(e) => {
+                transfer.fail();
+                format!("could not receive {}: {}", transfer.file_name, e)
+            }
+        };
+        self.messages.push(Message::notice(notice));
+    }
+    fn on_transfer_update(&mut self, update: TransferUpdate) {
+        let (id, peer) = match &update {
+            TransferUpdate::Sent { id, peer, .. } | TransferUpdate::Failed { id, peer, .. } => {
+                (id.clone(), peer.clone())
+            }
+        };
+        let Some(transfer) = self.transfers.iter_mut().find(|transfer| {
+            transfer.id == id
+                && transfer.direction == Direction::Outgoing
+                && transfer.peer.as_ref() == Some(&peer)
+        }) else {
+            return;
+        };
+        if !matches!(&update, TransferUpdate::Sent { sent, .. } if *sent < transfer.size) {
+            self.uploads.remove(&(id, peer.clone()));
+        }
+        match update {
+            TransferUpdate::Sent { sent, .. } if sent >= transfer.size => {
+                transfer.state = TransferState::Done;
+                self.messages.push(Message::notice(format!(
+                    "sent {} to {}",
+                    transfer.file_name, peer
+                )));
+            }
+            TransferUpdate::Sent { sent, .. } => transfer.state = TransferState::Active(sent),
+            TransferUpdate::Failed { reason, .. } => {
+                transfer.state = TransferState::Failed;
+                self.messages.push(Message::notice(format!(
+                    "could not send {} to {}: {}",
+                    transfer.file_name, peer, reason
+                )));
+            }
+        }
+    }
+    /// Joins the room selected in the connection manager
+    pub fn connect_selected_endpoint(&mut self) {
+        if let Some(link) = self
+            .endpoints
+            .selected()
+            .map(|endpoint| endpoint.link.clone())
+        {
+            let info = self.join_room(&link);
+            self.switch_mode(InputMode::Info(info));
+        }
     }
+    /// Opens the Command mode on a "save" command, describing the selected endpoint when editing it
+    pub fn edit_endpoint(&mut self, selected: bool) {
+        let mut command = String::from("save ");
+        if let Some(endpoint) = self.endpoints.selected().filter(|_| selected) {
+            command.push_str(&endpoint.link);
+            if let Some(nickname) = &endpoint.nickname {
+                command.push_str(&format!(" {}", nickname));
+            }
+            if let Some(fingerprint) = &endpoint.fingerprint {
+                command.push_str(&format!(" --fingerprint {}", fingerprint));
+            }
+        }
+        self.switch_mode(InputMode::Command);
+        self.text_buffer.set_value(&command);
+    }
+    /// Saves an endpoint, without a nickname or a fingerprint when none is given.
+    /// Returns the mode to switch to.
+    pub fn save_endpoint(&mut self, args: &[String]) -> InputMode {
+        let value = |i: usize| args.get(i).filter(|value| !value.is_empty()).cloned();
+        self.endpoints.save_endpoint(&args[0], value(1), value(2));
+        match self.endpoints.save() {
+            Ok(()) => InputMode::Connections,
+            Err(e) => InputMode::Info(format!("could not save the rooms: {}", e)),
+        }
+    }
+    pub fn delete_selected_endpoint(&mut self) {
+        if self.endpoints.remove_selected().is_some() {
+            if let Err(e) = self.endpoints.save() {
+                self.switch_mode(InputMode::Info(format!("could not save the rooms: {}", e)));
+            }
+        }
+    }
+    /// Runs the command in the input box, returns the mode to switch to, Err(()) to quit
+    pub fn execute_cmd(&mut self) -> Result<InputMode, ()> {
+        self.remember_input();
+        let input = self.text_buffer.value();
+        self.text_buffer.reset();
+        match parse_command(&input) {
+            Ok((command, args)) => (command.handler)(self, args),
+            Err(usage) => Ok(InputMode::Info(usage)),
+        }
+    }
+}
+
+/// The entries of the directory being typed, directories end with a slash
+fn path_candidates(word: &str) -> Vec<String> {
+    let (dir, _) = word.rsplit_once('/').unwrap_or_default();
+    let prefix = if word.contains('/') {
+        format!("{}/", dir)
+    } else {
+        String::new()
+    };
+    let path = match dir.strip_prefix('~').zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest.trim_start_matches('/')),
+        None if word.starts_with('/') && dir.is_empty() => PathBuf::from("/"),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(path) else {
+        return vec![];
+    };
+    let mut candidates = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", prefix, name, slash))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates
 }
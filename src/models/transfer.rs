@@ -0,0 +1,236 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Which way a file goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+    /// waiting for the receiver to accept or reject it
+    Offered,
+    /// bytes received or sent so far
+    Active(u64),
+    Done,
+    Rejected,
+    Failed,
+}
+
+/// A file offered by or to the local user
+#[derive(Debug)]
+pub struct Transfer {
+    pub id: String,
+    pub file_name: String,
+    pub size: u64,
+    pub direction: Direction,
+    /// the sender of an incoming file, the receiver of an outgoing one, None when offered to the room
+    pub peer: Option<String>,
+    pub state: TransferState,
+    /// the file being sent, or the one the received file is saved to
+    pub path: PathBuf,
+    // file the received chunks are written to, renamed once complete
+    part: Option<File>,
+}
+
+impl Transfer {
+    pub fn outgoing(id: String, path: PathBuf, size: u64, peer: Option<String>) -> Self {
+        Self {
+            id,
+            file_name: file_name(&path.to_string_lossy()),
+            size,
+            direction: Direction::Outgoing,
+            peer,
+            state: TransferState::Offered,
+            path,
+            part: None,
+        }
+    }
+    /// The name is sanitized, it comes from the peer
+    pub fn incoming(id: String, sender: String, name: &str, size: u64) -> Self {
+        Self {
+            id,
+            file_name: file_name(name),
+            size,
+            direction: Direction::Incoming,
+            peer: Some(sender),
+            state: TransferState::Offered,
+            path: PathBuf::new(),
+            part: None,
+        }
+    }
+    /// Prepares a file in the directory for the chunks to come, without overwriting any other
+    pub fn start_download(&mut self, dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(dir)?;
+        self.path = unique_path(dir, &self.file_name);
+        self.part = Some(File::create(self.part_path())?);
+        self.state = TransferState::Active(0);
+        Ok(())
+    }
+    /// Appends a received chunk, the file is complete once Ok(true) is returned.
+    /// A chunk coming after a missing one, or a second time, is skipped: the sender sends
+    /// the chunks again from the last byte acknowledged.
+    pub fn write_chunk(&mut self, offset: u64, data: &[u8]) -> Result<bool, String> {
+        let TransferState::Active(received) = self.state else {
+            return Err(String::from("the transfer is not in progress"));
+        };
+        if offset != received {
+            return Ok(false);
+        }
+        let received = received + data.len() as u64;
+        if received > self.size {
+            return Err(String::from("the file is larger than announced"));
+        }
+        let part = self.part.as_mut().ok_or("the file is not open")?;
+        part.write_all(data).map_err(|e| e.to_string())?;
+        self.state = TransferState::Active(received);
+        if received < self.size {
+            return Ok(false);
+        }
+        self.part = None;
+        fs::rename(self.part_path(), &self.path).map_err(|e| e.to_string())?;
+        self.state = TransferState::Done;
+        Ok(true)
+    }
+    /// Stops the transfer, the partially received file is removed
+    pub fn fail(&mut self) {
+        self.state = TransferState::Failed;
+        if self.part.take().is_some() {
+            let _ = fs::remove_file(self.part_path());
+        }
+    }
+    /// Percentage of the file transferred
+    pub fn progress(&self) -> u64 {
+        match self.state {
+            TransferState::Active(done) if self.size > 0 => done * 100 / self.size,
+            TransferState::Done => 100,
+            _ => 0,
+        }
+    }
+    fn part_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}.part", self.path.display()))
+    }
+}
+
+/// e.g. "12.5 KB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The last component of a path, safe to create in the downloads directory
+fn file_name(path: &str) -> String {
+    let name = path
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    match name.trim() {
+        "" | "." | ".." => String::from("file"),
+        _ => name,
+    }
+}
+
+/// The path of the name in the directory, numbered when a file already has the name
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    let mut path = dir.join(name);
+    let mut n = 1;
+    // a file of the same name may be being received
+    while path.exists() || Path::new(&format!("{}.part", path.display())).exists() {
+        path = dir.join(format!("{} ({}){}", stem, n, extension));
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::random_id;
+
+    /// A download of the size started in a directory of its own, removed by the caller
+    fn download(size: u64) -> (Transfer, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("endl-rc-{}", random_id()));
+        let mut transfer = Transfer::incoming(random_id(), String::from("bob"), "notes.txt", size);
+        transfer.start_download(&dir).unwrap();
+        (transfer, dir)
+    }
+
+    #[test]
+    fn writes_the_chunks_in_order() {
+        let (mut transfer, dir) = download(6);
+        assert_eq!(transfer.write_chunk(0, b"abc"), Ok(false));
+        // a chunk after a missing one, then one received twice, are skipped
+        assert_eq!(transfer.write_chunk(5, b"f"), Ok(false));
+        assert_eq!(transfer.write_chunk(0, b"abc"), Ok(false));
+        assert_eq!(transfer.state, TransferState::Active(3));
+        assert_eq!(transfer.write_chunk(3, b"def"), Ok(true));
+        assert_eq!(transfer.state, TransferState::Done);
+        let contents = fs::read(&transfer.path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(contents.unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn refuses_files_larger_than_announced() {
+        let (mut transfer, dir) = download(4);
+        assert_eq!(transfer.write_chunk(0, b"abc"), Ok(false));
+        assert!(transfer.write_chunk(3, b"de").is_err());
+        assert_eq!(transfer.state, TransferState::Active(3));
+        transfer.fail();
+        let part = transfer.part_path();
+        let left = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!part.exists());
+        assert_eq!(left, 0);
+        assert!(transfer.write_chunk(3, b"d").is_err());
+    }
+
+    #[test]
+    fn keeps_the_last_component_of_names() {
+        assert_eq!(file_name("notes.txt"), "notes.txt");
+        assert_eq!(file_name("../x"), "x");
+        assert_eq!(file_name("../../etc/passwd"), "passwd");
+        assert_eq!(file_name("C:\\x"), "x");
+        assert_eq!(file_name("C:\\Users\\bob\\..\\"), "file");
+        assert_eq!(file_name(".."), "file");
+        assert_eq!(file_name("dir/"), "file");
+        assert_eq!(file_name("a\u{1b}[2Jb"), "a[2Jb");
+    }
+
+    #[test]
+    fn numbers_names_already_taken() {
+        let dir = std::env::temp_dir().join(format!("endl-rc-{}", random_id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = unique_path(&dir, "notes.txt");
+        fs::write(&first, b"").unwrap();
+        fs::write(dir.join("notes (1).txt.part"), b"").unwrap();
+        let next = unique_path(&dir, "notes.txt");
+        let hidden = unique_path(&dir, ".bashrc");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first, dir.join("notes.txt"));
+        assert_eq!(next, dir.join("notes (2).txt"));
+        assert_eq!(hidden, dir.join(".bashrc"));
+    }
+}
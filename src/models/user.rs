@@ -1,17 +1,25 @@
 use ratatui::style::Color;
+use std::time::Duration;
 
 pub struct User {
     pub id: usize,
     pub name: String,
     pub color: Color,
+    /// last measured round-trip time, None for the root user or before the first pong
+    pub latency: Option<Duration>,
 }
 
-impl Default for User {
-    fn default() -> Self {
+impl User {
+    pub fn new(id: usize, name: String, color: Color) -> Self {
         Self {
-            id: 0,                     // root user
-            name: String::from("You"), // TODO: read user from file
-            color: Color::LightBlue,
+            id,
+            name,
+            color,
+            latency: None,
         }
     }
+    /// The user running the app, described by the profile
+    pub fn root(name: String, color: Color) -> Self {
+        Self::new(0, name, color)
+    }
 }
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time};
+
+use super::sanitize::{sanitize, MAX_NAME_CHARS};
+
+/// multicast group the hosts announce their rooms to
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 70, 77);
+const PORT: u16 = 7645;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// announcements are sent to the loopback interface too, so rooms are found on a single machine
+const INTERFACES: [Ipv4Addr; 2] = [Ipv4Addr::LOCALHOST, Ipv4Addr::UNSPECIFIED];
+
+/// What a host tells the local network about its room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    /// random id of the room, the same announcement is heard once per interface
+    pub id: String,
+    /// name of the user hosting the room
+    pub host: String,
+    pub room: String,
+    /// port the host is listening on, the address is the one the announcement came from
+    pub port: u16,
+    pub users: usize,
+    pub password: bool,
+}
+
+impl Announcement {
+    pub fn new(host: String, room: String, port: u16) -> Self {
+        Self {
+            id: format!("{:016x}", RandomState::new().build_hasher().finish()),
+            host,
+            room,
+            port,
+            users: 1,
+            // TODO: announce it once rooms can be protected
+            password: false,
+        }
+    }
+}
+
+/// A room heard on the network, along with the link to join it
+#[derive(Debug, Clone)]
+pub struct DiscoveredRoom {
+    pub link: String,
+    pub announcement: Announcement,
+}
+
+/// Announces the room every second until dropped, the user count is read before each announcement
+pub async fn announce(mut announcement: Announcement, users: impl Fn() -> usize) -> io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let mut interval = time::interval(ANNOUNCE_INTERVAL);
+    loop {
+        interval.tick().await;
+        announcement.users = users();
+        let payload = serde_json::to_vec(&announcement)?;
+        for interface in INTERFACES {
+            // an interface without multicast support should not prevent using the others
+            if SockRef::from(&socket)
+                .set_multicast_if_v4(&interface)
+                .is_ok()
+            {
+                let _ = socket.send_to(&payload, (GROUP, PORT)).await;
+            }
+        }
+    }
+}
+
+/// Listens to the announcements for a while, returns the rooms heard in the order they were
+pub async fn discover(duration: Duration) -> io::Result<Vec<DiscoveredRoom>> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // several instances may be discovering at the same time
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+    let joined = INTERFACES
+        .iter()
+        .filter(|interface| socket.join_multicast_v4(&GROUP, interface).is_ok())
+        .count();
+    if joined == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "multicast is not available",
+        ));
+    }
+    let socket = UdpSocket::from_std(socket.into())?;
+    let mut rooms: Vec<DiscoveredRoom> = vec![];
+    let mut buffer = [0; 2048];
+    let deadline = time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            received = socket.recv_from(&mut buffer) => {
+                let Ok((len, from)) = received else { continue };
+                let Ok(mut announcement) = serde_json::from_slice::<Announcement>(&buffer[..len]) else {
+                    continue;
+                };
+                if rooms.iter().any(|room| room.announcement.id == announcement.id) {
+                    continue;
+                }
+                announcement.host = clean_name(&announcement.host);
+                announcement.room = clean_name(&announcement.room);
+                rooms.push(DiscoveredRoom {
+                    link: format!("{}:{}", from.ip(), announcement.port),
+                    announcement,
+                });
+            }
+        }
+    }
+    Ok(rooms)
+}
+
+/// Names come from anyone on the network, they must not be able to mess with the terminal
+fn clean_name(name: &str) -> String {
+    sanitize(name, false)
+        .text
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect()
+}
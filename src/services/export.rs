@@ -0,0 +1,194 @@
+use chrono::SecondsFormat;
+use serde::Serialize;
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use crate::models::message::{Message, Reaction};
+
+/// How the messages are written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Txt,
+    Md,
+    Json,
+    Html,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "txt" => Ok(Self::Txt),
+            "md" => Ok(Self::Md),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            _ => Err(format!(
+                "unknown format \"{}\", expected txt, md, json or html",
+                name
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Txt => "txt",
+            Self::Md => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+    /// The format given, or the one the extension of the path stands for, text otherwise
+    pub fn resolve(format: Option<&str>, path: Option<&Path>) -> Result<Self, String> {
+        match format {
+            Some(format) => format.parse(),
+            None => Ok(path
+                .and_then(|path| path.extension()?.to_str()?.parse().ok())
+                .unwrap_or_default()),
+        }
+    }
+}
+
+/// A message as written in the json transcripts
+#[derive(Serialize)]
+struct Exported<'a> {
+    id: &'a str,
+    /// RFC 3339, in the local timezone
+    time: String,
+    sender: &'a str,
+    content: &'a str,
+    edited: bool,
+    deleted: bool,
+    reply_to: Option<&'a str>,
+    reactions: &'a [Reaction],
+}
+
+/// Writes out the messages, edits and deletions being applied, the notices left out
+pub fn transcript(messages: &[Message], format: ExportFormat, title: &str) -> String {
+    let messages = messages
+        .iter()
+        .filter(|msg| !msg.is_notice())
+        .collect::<Vec<_>>();
+    let senders = messages
+        .iter()
+        .map(|msg| (msg.id.as_str(), msg.source.as_str()))
+        .collect::<HashMap<_, _>>();
+    // whom a message answers to, when the parent is in the transcript
+    let parent = |msg: &Message| {
+        msg.reply_to
+            .as_ref()
+            .map(|parent| senders.get(parent.as_str()).copied().unwrap_or("?"))
+    };
+    let time = |msg: &Message| msg.local_time().format("%Y-%m-%d %H:%M:%S").to_string();
+    let content = |msg: &Message| {
+        if msg.deleted {
+            String::from("[deleted]")
+        } else {
+            msg.content.clone()
+        }
+    };
+    match format {
+        ExportFormat::Txt => {
+            let mut out = format!("{}\n\n", title);
+            for msg in messages {
+                let mut head = format!("{} <{}> ", time(msg), msg.source);
+                if let Some(parent) = parent(msg) {
+                    head.push_str(&format!("↪ {}: ", parent));
+                }
+                // the lines after the first start under it
+                let indent = " ".repeat(head.chars().count());
+                out.push_str(&head);
+                out.push_str(&content(msg).replace('\n', &format!("\n{}", indent)));
+                if msg.edited && !msg.deleted {
+                    out.push_str(" (edited)");
+                }
+                out.push('\n');
+            }
+            out
+        }
+        ExportFormat::Md => {
+            let mut out = format!("# {}\n", title);
+            for msg in messages {
+                out.push_str(&format!("\n**{}** · {}", msg.source, time(msg)));
+                if let Some(parent) = parent(msg) {
+                    out.push_str(&format!(" · in reply to {}", parent));
+                }
+                if msg.edited && !msg.deleted {
+                    out.push_str(" · *edited*");
+                }
+                out.push_str("\n\n");
+                out.push_str(&content(msg));
+                out.push('\n');
+            }
+            out
+        }
+        ExportFormat::Json => {
+            let exported = messages
+                .iter()
+                .map(|msg| Exported {
+                    id: &msg.id,
+                    time: msg
+                        .local_time()
+                        .to_rfc3339_opts(SecondsFormat::Millis, false),
+                    sender: &msg.source,
+                    content: &msg.content,
+                    edited: msg.edited,
+                    deleted: msg.deleted,
+                    reply_to: msg.reply_to.as_deref(),
+                    reactions: &msg.reactions,
+                })
+                .collect::<Vec<_>>();
+            let mut out = serde_json::to_string_pretty(&exported).unwrap_or_default();
+            out.push('\n');
+            out
+        }
+        ExportFormat::Html => {
+            let mut out = format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+                 <style>body {{ font-family: monospace; }} .content {{ white-space: pre-wrap; }} \
+                 .muted {{ color: gray; }}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+                escape_html(title),
+                escape_html(title)
+            );
+            for msg in messages {
+                out.push_str(&format!(
+                    "<p><span class=\"muted\">{}</span> <b>{}</b>",
+                    time(msg),
+                    escape_html(&msg.source)
+                ));
+                if let Some(parent) = parent(msg) {
+                    out.push_str(&format!(
+                        " <span class=\"muted\">↪ {}</span>",
+                        escape_html(parent)
+                    ));
+                }
+                out.push_str(&format!(
+                    " <span class=\"content\">{}</span>",
+                    escape_html(&content(msg))
+                ));
+                if msg.edited && !msg.deleted {
+                    out.push_str(" <span class=\"muted\">(edited)</span>");
+                }
+                out.push_str("</p>\n");
+            }
+            out.push_str("</body>\n</html>\n");
+            out
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,2 +1,9 @@
+pub mod discovery;
+pub mod export;
+pub mod preview;
+pub mod protocol;
+pub mod sanitize;
 pub mod server;
 pub mod server_commands;
+pub mod server_events;
+pub mod transfer;
@@ -0,0 +1,363 @@
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{lookup_host, TcpStream},
+    time,
+};
+
+use crate::models::message::LinkPreview;
+
+use super::sanitize::sanitize;
+
+/// a page taking longer than that to come is not worth waiting for
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// the titles and descriptions are in the head of the pages, the rest is never read
+const MAX_RESPONSE: u64 = 256 * 1024;
+const MAX_TITLE_CHARS: usize = 80;
+const MAX_DESCRIPTION_CHARS: usize = 160;
+
+/// Whether the link leads to one of the domains, or to one of their subdomains
+pub fn is_allowed(url: &str, domains: &[String]) -> bool {
+    let Some(host) = host_of(url) else {
+        return false;
+    };
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        host == domain
+            || host
+                .strip_suffix(&domain)
+                .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+/// The lowercase host name of an http or https URL, without credentials nor port
+fn host_of(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Fetches the title and the description of a page through an HTTP proxy of this machine.
+/// The proxy is sent the full URL and has to reach https pages by itself.
+pub async fn fetch_preview(url: String, proxy: String) -> Result<LinkPreview, String> {
+    let host = host_of(&url).ok_or("not a web link")?;
+    let proxy = lookup_host(&proxy)
+        .await
+        .map_err(|e| format!("invalid proxy {}: {}", proxy, e))?
+        .collect::<Vec<_>>();
+    let proxy = local_proxy(&proxy).ok_or("the proxy must be on this machine")?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: endl-rc\r\nAccept: text/html\r\nConnection: close\r\n\r\n",
+        url, host
+    );
+    let response = time::timeout(FETCH_TIMEOUT, async {
+        let mut stream = TcpStream::connect(proxy).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = vec![];
+        stream.take(MAX_RESPONSE).read_to_end(&mut response).await?;
+        Ok::<_, std::io::Error>(response)
+    })
+    .await
+    .map_err(|_| String::from("timed out"))?
+    .map_err(|e| e.to_string())?;
+    let body = parse_response(&response)?;
+    parse_preview(url, &body).ok_or_else(|| String::from("no title"))
+}
+
+/// The address of the proxy, when all the addresses its name resolves to are of this machine:
+/// the previews must not go around the proxy, nor through a remote machine
+fn local_proxy(addresses: &[SocketAddr]) -> Option<SocketAddr> {
+    addresses
+        .first()
+        .filter(|_| addresses.iter().all(|a| a.ip().is_loopback()))
+        .copied()
+}
+
+/// The body of a successful response, decoded when sent in chunks.
+/// The response may be cut short, what came of the body is kept.
+fn parse_response(response: &[u8]) -> Result<String, String> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or("invalid response")?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let body = &response[end + 4..];
+    let status = head.split_whitespace().nth(1);
+    if status != Some("200") {
+        return Err(format!("status {}", status.unwrap_or("missing")));
+    }
+    let chunked = head.lines().skip(1).any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.to_ascii_lowercase().contains("chunked")
+        })
+    });
+    Ok(if chunked {
+        String::from_utf8_lossy(&dechunk(body)).into_owned()
+    } else {
+        String::from_utf8_lossy(body).into_owned()
+    })
+}
+
+/// The data of a body sent in chunks, each one being preceded by its size in hexadecimal
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut data = vec![];
+    while let Some(end) = body.windows(2).position(|window| window == b"\r\n") {
+        let line = String::from_utf8_lossy(&body[..end]);
+        // the size may be followed by extensions
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let Ok(size) = usize::from_str_radix(size, 16) else {
+            break;
+        };
+        if size == 0 {
+            break;
+        }
+        body = &body[end + 2..];
+        let chunk = &body[..size.min(body.len())];
+        data.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+    data
+}
+
+/// Reads the Open Graph title and description of a page, falling back to its title tag
+fn parse_preview(url: String, html: &str) -> Option<LinkPreview> {
+    // lowercasing ascii keeps the positions the same in both texts
+    let lower = html.to_ascii_lowercase();
+    let mut title = None;
+    let mut description = None;
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find("<meta") {
+        let start = from + pos;
+        let end = lower[start..]
+            .find('>')
+            .map_or(lower.len(), |end| start + end);
+        let tag = &html[start..end];
+        let key = attribute(tag, "property")
+            .or_else(|| attribute(tag, "name"))
+            .map(str::to_ascii_lowercase);
+        match (key.as_deref(), attribute(tag, "content")) {
+            (Some("og:title"), Some(content)) => title = Some(content),
+            (Some("og:description"), Some(content)) => description = Some(content),
+            (Some("description"), Some(content)) if description.is_none() => {
+                description = Some(content)
+            }
+            _ => {}
+        }
+        from = end;
+    }
+    let title = title.or_else(|| {
+        let start = lower.find("<title")?;
+        let start = start + lower[start..].find('>')? + 1;
+        let end = start + lower[start..].find("</title")?;
+        Some(&html[start..end])
+    })?;
+    let title = Some(clean(title, MAX_TITLE_CHARS)).filter(|title| !title.is_empty())?;
+    Some(LinkPreview {
+        url,
+        title,
+        description: description
+            .map(|description| clean(description, MAX_DESCRIPTION_CHARS))
+            .filter(|description| !description.is_empty()),
+    })
+}
+
+/// The value of an attribute of a tag, quoted or not
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(name) {
+        let start = from + pos;
+        from = start + name.len();
+        let preceded = lower[..start].ends_with(char::is_whitespace);
+        let Some(value) = lower[from..]
+            .trim_start()
+            .strip_prefix('=')
+            .filter(|_| preceded)
+        else {
+            continue;
+        };
+        let value_start = tag.len() - value.trim_start().len();
+        let value = &tag[value_start..];
+        return match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].split(quote).next(),
+            _ => value.split(|c: char| c.is_whitespace() || c == '/').next(),
+        };
+    }
+    None
+}
+
+/// Decodes the entities of a text from a page, then makes it a single safe line of at most
+/// the given length
+fn clean(text: &str, max_chars: usize) -> String {
+    let text = decode_entities(text);
+    let text = sanitize(&text, false).text;
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut line = words.chars().take(max_chars).collect::<String>();
+    if words.chars().count() > max_chars {
+        line.push('…');
+    }
+    line
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        decoded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|len| *len <= 8)
+            .map(|len| &rest[1..len + 1]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#')?.parse().ok())
+                .and_then(char::from_u32),
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains(domains: &[&str]) -> Vec<String> {
+        domains.iter().map(|domain| domain.to_string()).collect()
+    }
+
+    #[test]
+    fn allows_the_domains_and_their_subdomains() {
+        let allowed = domains(&["example.com", ".Wiki.org"]);
+        assert!(is_allowed("https://example.com", &allowed));
+        assert!(is_allowed("http://docs.Example.com/a?b#c", &allowed));
+        assert!(is_allowed("https://en.wiki.org:443/page", &allowed));
+        assert!(is_allowed("https://user:pw@example.com/", &allowed));
+        assert!(!is_allowed("https://notexample.com", &allowed));
+        assert!(!is_allowed("https://example.com.evil.net", &allowed));
+        assert!(!is_allowed("https://evil.net/example.com", &allowed));
+        assert!(!is_allowed("https://example.com@evil.net", &allowed));
+        assert!(!is_allowed("ftp://example.com", &allowed));
+        assert!(!is_allowed("https://", &allowed));
+        assert!(!is_allowed("https://example.com", &[]));
+        assert!(is_allowed("http://[::1]:8080/", &domains(&["::1"])));
+    }
+
+    #[test]
+    fn only_uses_proxies_of_this_machine() {
+        let local: SocketAddr = "127.0.0.1:8118".parse().unwrap();
+        let local_v6: SocketAddr = "[::1]:8118".parse().unwrap();
+        let remote: SocketAddr = "192.168.1.10:8118".parse().unwrap();
+        assert_eq!(local_proxy(&[local]), Some(local));
+        assert_eq!(local_proxy(&[local_v6, local]), Some(local_v6));
+        assert_eq!(local_proxy(&[local, remote]), None);
+        assert_eq!(local_proxy(&[remote]), None);
+        assert_eq!(local_proxy(&[]), None);
+    }
+
+    #[test]
+    fn reads_the_body_of_successful_responses() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<title>Hi</title>";
+        assert_eq!(parse_response(response).unwrap(), "<title>Hi</title>");
+        let response = b"HTTP/1.1 404 Not Found\r\n\r\n<title>Missing</title>";
+        assert_eq!(parse_response(response).unwrap_err(), "status 404");
+        assert_eq!(parse_response(b"\r\n\r\n").unwrap_err(), "status missing");
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\n").unwrap_err(),
+            "invalid response"
+        );
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let response = b"HTTP/1.1 200 OK\r\ntransfer-encoding: Chunked\r\n\r\n\
+            7\r\n<title>\r\na;ext=1\r\nHello worl\r\n9\r\nd</title>\r\n0\r\n\r\n";
+        assert_eq!(
+            parse_response(response).unwrap(),
+            "<title>Hello world</title>"
+        );
+        // a response cut short keeps what came
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            7\r\n<title>\r\n100\r\nHello";
+        assert_eq!(parse_response(response).unwrap(), "<title>Hello");
+    }
+
+    #[test]
+    fn prefers_the_open_graph_tags() {
+        let html = "<html><head><title>Page</title>\
+            <META Property=\"og:title\" content='Shared &amp; titled'>\
+            <meta name=description content=Plain/>\
+            <meta property=\"og:description\" content=\"Told\n  in  full\"></head>";
+        let preview = parse_preview(String::from("https://a.b"), html).unwrap();
+        assert_eq!(preview.url, "https://a.b");
+        assert_eq!(preview.title, "Shared & titled");
+        assert_eq!(preview.description.as_deref(), Some("Told in full"));
+    }
+
+    #[test]
+    fn falls_back_to_the_title_tag() {
+        let html = "<head><TITLE lang=en> A\tpage </TITLE>\
+            <meta name=\"description\" content=\"Described\"><meta data-name=\"x\"></head>";
+        let preview = parse_preview(String::new(), html).unwrap();
+        assert_eq!(preview.title, "A page");
+        assert_eq!(preview.description.as_deref(), Some("Described"));
+        assert!(parse_preview(String::new(), "<title> </title>").is_none());
+        assert!(parse_preview(String::new(), "<p>no title</p>").is_none());
+    }
+
+    #[test]
+    fn shortens_and_sanitizes_the_texts() {
+        let html = format!("<title>{}\u{1b}[2J</title>", "a".repeat(100));
+        let title = parse_preview(String::new(), &html).unwrap().title;
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS + 1);
+        assert!(title.ends_with('…'));
+        let title = parse_preview(String::new(), "<title>x\u{1b}[2J</title>")
+            .unwrap()
+            .title;
+        assert!(!title.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("&lt;b&gt; &quot;a&quot; &apos;b&apos; &amp;&nbsp;"),
+            "<b> \"a\" 'b' & "
+        );
+        assert_eq!(decode_entities("&#233;&#xE9;&#X1F600;"), "éé😀");
+        assert_eq!(decode_entities("AT&T; a & b"), "AT&T; a & b");
+        assert_eq!(
+            decode_entities("&#xD800; &#99999999;"),
+            "&#xD800; &#99999999;"
+        );
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+        assert_eq!(decode_entities("trailing &"), "trailing &");
+    }
+}
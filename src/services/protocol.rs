@@ -0,0 +1,140 @@
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::models::message::{LinkPreview, Message};
+
+/// longest line read from a peer, a longer one is taken for an attack and drops the link
+pub const MAX_FRAME: usize = 64 * 1024;
+/// left in the frames for the host to sign them with the name and the time of their sender
+const SIGNATURE_ROOM: usize = 1024;
+
+/// A unit of data exchanged between peers.
+/// Frames are serialized as JSON, one frame per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Frame {
+    /// first frame sent on a link, introduces a user to the room
+    Hello {
+        name: String,
+        color: Color,
+        /// secret the host recognizes the messages of the user by, across reconnections,
+        /// only ever sent to the host
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        key: String,
+    },
+    /// sent by the host when a user leaves the room
+    Leave {
+        name: String,
+    },
+    /// sent by the host before closing a link it does not accept,
+    /// or when it does not accept a frame about a message
+    Refused {
+        reason: String,
+        /// the message, the link itself is refused when unset
+        #[serde(default)]
+        id: Option<String>,
+    },
+    Chat(Message),
+    /// sent by the host to the author of a message once it reached the room
+    Ack {
+        id: String,
+        /// the time the host stamped the message with, the reference for the whole room
+        #[serde(default)]
+        timestamp: Option<i64>,
+    },
+    /// replaces the content of a message, only accepted from its author
+    Edit {
+        id: String,
+        content: String,
+        /// set by the peers that had to sanitize the new content
+        #[serde(default)]
+        suspicious: bool,
+    },
+    /// only accepted from the author of the message
+    Delete {
+        id: String,
+    },
+    /// adds or removes the reaction of a user to a message
+    React {
+        id: String,
+        emoji: String,
+        name: String,
+        add: bool,
+    },
+    Ping {
+        nonce: u64,
+    },
+    Pong {
+        nonce: u64,
+    },
+    /// sent when a user starts or stops typing
+    Typing {
+        name: String,
+        typing: bool,
+    },
+    /// offers a file to the room, or to a single user
+    FileOffer {
+        id: String,
+        name: String,
+        to: Option<String>,
+        file_name: String,
+        size: u64,
+    },
+    /// sent to the user who offered a file
+    FileAnswer {
+        id: String,
+        name: String,
+        to: String,
+        accept: bool,
+    },
+    /// a piece of a file, sent to a user who accepted it
+    FileChunk {
+        id: String,
+        name: String,
+        to: String,
+        /// position of the piece in the file
+        offset: u64,
+        /// the bytes in base64
+        data: String,
+        checksum: u64,
+    },
+    /// sent to the user sending a file, each time a chunk is written
+    FileAck {
+        id: String,
+        name: String,
+        to: String,
+        /// bytes of the file received so far
+        received: u64,
+    },
+    /// sent by the host, what a link of a message leads to
+    LinkPreview {
+        id: String,
+        preview: LinkPreview,
+    },
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = serde_json::to_vec(self).unwrap_or_default();
+        bytes.push(b'\n');
+        bytes
+    }
+    /// The user a frame is meant for, None when it is meant for the whole room
+    pub fn recipient(&self) -> Option<&str> {
+        match self {
+            Frame::FileOffer { to, .. } => to.as_deref(),
+            Frame::FileAnswer { to, .. }
+            | Frame::FileChunk { to, .. }
+            | Frame::FileAck { to, .. } => Some(to),
+            _ => None,
+        }
+    }
+    /// Whether the frame is short enough to be relayed by the host
+    pub fn fits(&self) -> bool {
+        self.encode().len() + SIGNATURE_ROOM <= MAX_FRAME
+    }
+    /// Decodes a single line, returns None for malformed frames
+    pub fn decode(line: &str) -> Option<Self> {
+        serde_json::from_str(line.trim_end()).ok()
+    }
+}
@@ -0,0 +1,272 @@
+use unicode_width::UnicodeWidthChar;
+
+use super::protocol::Frame;
+
+/// combining marks, joiners and other zero-width characters kept after a visible character,
+/// enough for accents, scripts stacking vowel signs and emoji sequences
+const MAX_ZERO_WIDTH_RUN: usize = 6;
+/// spaces a tab is replaced with
+const TAB: &str = "    ";
+/// longest user or room name kept from a peer
+pub const MAX_NAME_CHARS: usize = 32;
+
+/// Text made safe to render
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitized {
+    pub text: String,
+    /// whether the text held something meant to mess with the display or to mislead
+    pub suspicious: bool,
+}
+
+/// Makes text coming from a peer safe to render.
+/// Control characters, escape sequences included, and bidi overrides are visibly escaped,
+/// invisible characters are dropped and runs of combining marks are cut short.
+/// New lines are only kept in multiline text, they are spaces otherwise.
+pub fn sanitize(text: &str, multiline: bool) -> Sanitized {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut suspicious = false;
+    // zero-width characters since the last visible one
+    let mut run = 0;
+    for c in text.chars() {
+        match c {
+            '\n' | '\u{2028}' | '\u{2029}' => {
+                sanitized.push(if multiline { '\n' } else { ' ' });
+                run = 0;
+            }
+            '\t' => {
+                sanitized.push_str(TAB);
+                run = 0;
+            }
+            // line endings of some platforms
+            '\r' => {}
+            c if c.is_control() || is_bidi_control(c) => {
+                sanitized.push_str(&escape(c));
+                suspicious = true;
+                run = 0;
+            }
+            c if is_invisible(c) || c.width() == Some(0) => {
+                run += 1;
+                if run > MAX_ZERO_WIDTH_RUN {
+                    suspicious = true;
+                } else if !is_invisible(c) {
+                    sanitized.push(c);
+                }
+            }
+            c => {
+                sanitized.push(c);
+                run = 0;
+            }
+        }
+    }
+    Sanitized {
+        text: sanitized,
+        suspicious,
+    }
+}
+
+/// Sanitizes every text of a frame received from a peer, flagging the suspicious messages
+pub fn sanitize_frame(frame: &mut Frame) {
+    let line = |text: &mut String| *text = sanitize(text, false).text;
+    match frame {
+        Frame::Chat(msg) => {
+            let content = sanitize(&msg.content, true);
+            msg.content = content.text;
+            msg.suspicious |= content.suspicious;
+            line(&mut msg.id);
+            line(&mut msg.source);
+            if let Some(parent) = &mut msg.reply_to {
+                line(parent);
+            }
+            for reaction in &mut msg.reactions {
+                line(&mut reaction.emoji);
+                reaction.users.iter_mut().for_each(line);
+            }
+        }
+        Frame::Edit {
+            id,
+            content,
+            suspicious,
+        } => {
+            let sanitized = sanitize(content, true);
+            *content = sanitized.text;
+            *suspicious |= sanitized.suspicious;
+            line(id);
+        }
+        Frame::React {
+            id, emoji, name, ..
+        } => {
+            line(id);
+            line(emoji);
+            line(name);
+        }
+        Frame::FileOffer {
+            id,
+            name,
+            to,
+            file_name,
+            ..
+        } => {
+            line(id);
+            line(name);
+            line(file_name);
+            if let Some(to) = to {
+                line(to);
+            }
+        }
+        Frame::FileAnswer { id, name, to, .. }
+        | Frame::FileChunk { id, name, to, .. }
+        | Frame::FileAck { id, name, to, .. } => {
+            line(id);
+            line(name);
+            line(to);
+        }
+        Frame::LinkPreview { id, preview } => {
+            line(id);
+            line(&mut preview.url);
+            line(&mut preview.title);
+            if let Some(description) = &mut preview.description {
+                line(description);
+            }
+        }
+        Frame::Ack { id, .. } | Frame::Delete { id } => line(id),
+        Frame::Refused { reason, id } => {
+            line(reason);
+            if let Some(id) = id {
+                line(id);
+            }
+        }
+        // the host signs the frames of a user with the name it introduced itself with
+        Frame::Hello { name, key, .. } => {
+            line(key);
+            line(name);
+            *name = name.chars().take(MAX_NAME_CHARS).collect();
+        }
+        Frame::Leave { name } | Frame::Typing { name, .. } => line(name),
+        Frame::Ping { .. } | Frame::Pong { .. } => {}
+    }
+}
+
+/// Embeddings, overrides, isolates and marks, which can make text read differently than it is written
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200E}' | '\u{200F}' | '\u{061C}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// Characters without any glyph, which can hide differences between look-alike texts.
+/// The joiners are not among them, emoji sequences and some scripts need them.
+fn is_invisible(c: char) -> bool {
+    let invisible_operators = '\u{2060}'..='\u{2064}';
+    invisible_operators.contains(&c)
+        || matches!(
+            c,
+            '\u{200B}'
+                | '\u{FEFF}'
+                | '\u{180E}'
+                | '\u{115F}'
+                | '\u{1160}'
+                | '\u{3164}'
+                | '\u{FFA0}'
+        )
+}
+
+/// e.g. "␛" for the escape character, "<U+202E>" for a right-to-left override
+fn escape(c: char) -> String {
+    match c as u32 {
+        code @ 0..=0x1F => char::from_u32(0x2400 + code).unwrap_or('?').to_string(),
+        0x7F => String::from("␡"),
+        code => format!("<U+{:04X}>", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+
+    fn clean(text: &str) -> Sanitized {
+        Sanitized {
+            text: text.to_owned(),
+            suspicious: false,
+        }
+    }
+
+    #[test]
+    fn keeps_plain_text() {
+        assert_eq!(sanitize("hello, wörld 👋", false), clean("hello, wörld 👋"));
+        assert_eq!(sanitize("👩\u{200D}💻", false), clean("👩\u{200D}💻"));
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        let sanitized = sanitize("\x1b[31mred\x07", true);
+        assert_eq!(sanitized.text, "␛[31mred␇");
+        assert!(sanitized.suspicious);
+        assert_eq!(sanitize("a\x7fb", true).text, "a␡b");
+        assert_eq!(sanitize("a\u{85}b", true).text, "a<U+0085>b");
+    }
+
+    #[test]
+    fn escapes_bidi_controls() {
+        let sanitized = sanitize("invoice_\u{202E}fdp.exe", false);
+        assert_eq!(sanitized.text, "invoice_<U+202E>fdp.exe");
+        assert!(sanitized.suspicious);
+        assert!(sanitize("\u{2066}isolated\u{2069}", false).suspicious);
+        // the marks reorder the text around them too
+        let sanitized = sanitize("abc\u{200F}.exe\u{200E}\u{061C}", false);
+        assert_eq!(sanitized.text, "abc<U+200F>.exe<U+200E><U+061C>");
+        assert!(sanitized.suspicious);
+    }
+
+    #[test]
+    fn keeps_new_lines_in_multiline_text_only() {
+        assert_eq!(sanitize("one\r\ntwo\u{2028}", true), clean("one\ntwo\n"));
+        assert_eq!(sanitize("one\r\ntwo\u{2028}", false), clean("one two "));
+    }
+
+    #[test]
+    fn replaces_tabs() {
+        assert_eq!(sanitize("a\tb", false), clean("a    b"));
+    }
+
+    #[test]
+    fn drops_invisible_characters() {
+        assert_eq!(sanitize("ad\u{200B}min\u{FEFF}", false), clean("admin"));
+    }
+
+    #[test]
+    fn cuts_runs_of_combining_marks() {
+        let marks = |count: usize| "\u{301}".repeat(count);
+        let allowed = format!("e{}", marks(MAX_ZERO_WIDTH_RUN));
+        assert_eq!(sanitize(&allowed, false), clean(&allowed));
+        let sanitized = sanitize(&format!("e{}", marks(MAX_ZERO_WIDTH_RUN + 1)), false);
+        assert_eq!(sanitized.text, allowed);
+        assert!(sanitized.suspicious);
+        // a visible character starts a new run
+        let runs = format!("{}{}", allowed, allowed);
+        assert_eq!(sanitize(&runs, false), clean(&runs));
+    }
+
+    #[test]
+    fn counts_invisible_characters_in_runs() {
+        let sanitized = sanitize(&"\u{200B}".repeat(MAX_ZERO_WIDTH_RUN + 1), false);
+        assert_eq!(sanitized.text, "");
+        assert!(sanitized.suspicious);
+    }
+
+    #[test]
+    fn truncates_names() {
+        let mut frame = Frame::Hello {
+            name: format!("{}\x1b", "a".repeat(MAX_NAME_CHARS + 8)),
+            color: Color::Red,
+            key: String::new(),
+        };
+        sanitize_frame(&mut frame);
+        let Frame::Hello { name, .. } = frame else {
+            unreachable!()
+        };
+        assert_eq!(name, "a".repeat(MAX_NAME_CHARS));
+    }
+}
@@ -1,82 +1,537 @@
+use chrono::Utc;
 use ratatui::style::Color;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
     net::{TcpListener, TcpStream},
-    sync::broadcast,
+    sync::broadcast::{self, error::RecvError},
+    task::JoinSet,
+    time,
+};
+
+use crate::{
+    models::{
+        config::{NetworkConfig, ProfileConfig},
+        emoji::parse_reaction,
+        message::{random_id, Message},
+    },
+    views::colors::user_color,
 };
 
-use crate::models::message::Message;
+use super::{
+    discovery::{announce, Announcement},
+    protocol::{Frame, MAX_FRAME},
+    sanitize::sanitize_frame,
+    server_commands::ServerCommand,
+    server_events::ServerEvent,
+};
+
+/// A frame travelling through a room, tagged with the id of the link it came from
+type Routed = (usize, Frame);
+/// id of the frames written by the local app
+const LOCAL_ID: usize = 0;
+/// why an edit or a deletion is refused
+const NOT_AUTHOR: &str = "only the author of a message can modify it";
+/// why a message reusing the id of another one is refused
+const ID_TAKEN: &str = "the message was not sent, another one has the same id";
+/// messages the host keeps track of, the older ones can no longer be edited or retried
+const MAX_RELAYED: usize = 10_000;
+
+/// State shared by the links of a hosted room
+#[derive(Clone)]
+struct Relay {
+    tx: broadcast::Sender<Routed>,
+    /// hello frames of the users currently in the room
+    members: Arc<Mutex<Vec<Frame>>>,
+    relayed: Arc<Mutex<RelayedMessages>>,
+}
+
+/// The latest messages relayed, by id
+#[derive(Default)]
+struct RelayedMessages {
+    by_id: HashMap<String, Relayed>,
+    /// ids from the oldest message to the newest
+    order: VecDeque<String>,
+}
+
+impl RelayedMessages {
+    fn get(&self, msg_id: &str) -> Option<&Relayed> {
+        self.by_id.get(msg_id)
+    }
+    /// Records a message, forgetting the oldest one when there are too many
+    fn insert(&mut self, msg_id: String, msg: Relayed) {
+        if self.by_id.insert(msg_id.clone(), msg).is_none() {
+            self.order.push_back(msg_id);
+        }
+        if self.order.len() > MAX_RELAYED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_id.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// A message that reached the room
+struct Relayed {
+    /// key of the user who sent it, names can be taken again once their users left
+    author: String,
+    /// the time the host stamped the message with
+    timestamp: i64,
+}
 
-use super::server_commands::ServerCommand;
+impl Relay {
+    fn is_author(&self, msg_id: &str, key: &str) -> bool {
+        let relayed = self.relayed.lock().unwrap();
+        relayed.get(msg_id).is_some_and(|msg| msg.author == key)
+    }
+    /// Whether a user of that name is in the room, the host included
+    fn is_taken(&self, name: &str, host: &Frame) -> bool {
+        let members = self.members.lock().unwrap();
+        let is_named =
+            |hello: &Frame| matches!(hello, Frame::Hello { name: taken, .. } if taken == name);
+        is_named(host) || members.iter().any(is_named)
+    }
+    fn is_known(&self, msg_id: &str) -> bool {
+        self.relayed.lock().unwrap().get(msg_id).is_some()
+    }
+    /// The time a message of that author was stamped with
+    fn stamped(&self, msg_id: &str, author: &str) -> Option<i64> {
+        let relayed = self.relayed.lock().unwrap();
+        relayed
+            .get(msg_id)
+            .filter(|msg| msg.author == author)
+            .map(|msg| msg.timestamp)
+    }
+    fn record(&self, msg: &Message, author: &str) {
+        self.relayed.lock().unwrap().insert(
+            msg.id.clone(),
+            Relayed {
+                author: author.to_owned(),
+                timestamp: msg.timestamp,
+            },
+        );
+    }
+}
+
+/// What a link needs to know about the room it belongs to
+#[derive(Clone)]
+struct RoomContext {
+    config: NetworkConfig,
+    /// introduces the local user to the peer
+    hello: Frame,
+    /// proves the local user wrote its messages, kept when reconnecting
+    key: String,
+    server_app_messages_tx: broadcast::Sender<ServerEvent>,
+    /// only set when hosting
+    relay: Option<Relay>,
+}
 
 pub struct Server {
     session_link: String,
+    config: NetworkConfig,
+    profile: ProfileConfig,
 }
 
 impl Server {
-    pub fn new() -> Server {
+    pub fn new(config: NetworkConfig, profile: ProfileConfig) -> Server {
         Server {
             session_link: String::from("localhost:8080"),
+            config,
+            profile,
+        }
+    }
+    fn room_context(
+        &self,
+        server_app_messages_tx: broadcast::Sender<ServerEvent>,
+        relay: Option<Relay>,
+    ) -> RoomContext {
+        RoomContext {
+            config: self.config.clone(),
+            hello: Frame::Hello {
+                name: self.profile.name.clone(),
+                color: user_color(&self.profile.name, self.profile.color),
+                key: String::new(),
+            },
+            key: random_id(),
+            server_app_messages_tx,
+            relay,
         }
     }
     async fn join(
         &mut self,
         link: String,
-        server_app_messages_tx: broadcast::Sender<Message>,
-        app_server_messages_tx: broadcast::Sender<Message>,
+        server_app_messages_tx: broadcast::Sender<ServerEvent>,
+        app_server_messages_tx: broadcast::Sender<Frame>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // TODO
         self.session_link = link;
-        let socket: TcpStream = TcpStream::connect(self.session_link.clone()).await?;
-        Self::handle_client(socket, server_app_messages_tx, app_server_messages_tx).await;
-        Ok(())
+        let room = self.room_context(server_app_messages_tx.clone(), None);
+        let mut attempt = 0;
+        let mut connected_once = false;
+        loop {
+            match TcpStream::connect(self.session_link.clone()).await {
+                Ok(socket) => {
+                    connected_once = true;
+                    attempt = 0;
+                    let _ = server_app_messages_tx
+                        .send(ServerEvent::Connected(self.session_link.clone()));
+                    let (routed_tx, routed_rx) = broadcast::channel::<Routed>(16);
+                    tokio::select! {
+                        refused = Self::handle_client(
+                            socket,
+                            self.session_link.clone(),
+                            LOCAL_ID + 1,
+                            routed_rx,
+                            room.clone(),
+                        ) => {
+                            if let Some(reason) = refused {
+                                // joining again would be refused all the same
+                                let _ = server_app_messages_tx.send(ServerEvent::Disconnected(reason));
+                                return Ok(());
+                            }
+                        }
+                        _ = Self::forward_app_frames(app_server_messages_tx.subscribe(), routed_tx, room.clone()) => {}
+                    }
+                }
+                // a link that never worked is a wrong link, there is nothing to reconnect to
+                Err(e) if !connected_once => {
+                    let _ = server_app_messages_tx.send(ServerEvent::Disconnected(format!(
+                        "could not reach {}: {}",
+                        self.session_link, e
+                    )));
+                    return Err(e.into());
+                }
+                Err(_) => {}
+            }
+            if attempt == self.config.reconnect_attempts {
+                let _ = server_app_messages_tx.send(ServerEvent::Disconnected(format!(
+                    "lost connection to {}",
+                    self.session_link
+                )));
+                return Ok(());
+            }
+            attempt += 1;
+            let _ = server_app_messages_tx.send(ServerEvent::Reconnecting(attempt));
+            time::sleep(self.config.reconnect_delay()).await;
+        }
     }
+    /// Exchanges frames with a peer until it disconnects or stops answering pings.
+    /// Frames received from the peer are sent to the app and, when hosting, relayed to the room.
+    /// Returns why the host refused the link, if it did.
     async fn handle_client(
-        mut socket: tokio::net::TcpStream,
-        server_app_messages_tx: broadcast::Sender<Message>,
-        app_server_messages_tx: broadcast::Sender<Message>,
-    ) {
+        mut socket: TcpStream,
+        mut peer: String,
+        id: usize,
+        mut routed_rx: broadcast::Receiver<Routed>,
+        room: RoomContext,
+    ) -> Option<String> {
         let (socket_reader, mut socket_writer) = socket.split();
-        let mut buff_reader = BufReader::new(socket_reader);
+        // a line never ending would otherwise fill the memory
+        let mut buff_reader = BufReader::new(socket_reader).take(MAX_FRAME as u64);
         let mut line = String::new();
-        let mut app_server_messages_rx = app_server_messages_tx.subscribe();
+        let mut heartbeat = time::interval(room.config.ping_interval());
+        let mut last_seen = Instant::now();
+        let mut pending_ping: Option<(u64, Instant)> = None;
+        let mut next_nonce: u64 = 0;
+        // name and color the peer introduced itself with
+        let mut joined_as: Option<(String, Color)> = None;
+        // key the messages of the peer are recorded with
+        let mut author = String::new();
+        let mut refused = None;
+        let mut hello = room.hello.clone();
+        if let (Frame::Hello { key, .. }, None) = (&mut hello, &room.relay) {
+            *key = room.key.clone();
+        }
+        if socket_writer.write_all(&hello.encode()).await.is_err() {
+            return None;
+        }
         loop {
             tokio::select! {
                 // socket incoming messages
                 bytes_read = buff_reader.read_line(&mut line) => {
-                    if let Ok(0) = bytes_read { break; }
-                    let new_msg = line.clone();
-                    // TODO: actually de-serialize the incoming message to figure out which user sent it
-                    if server_app_messages_tx.send(Message::new(new_msg, Color::LightYellow, String::from("Program"))).is_err() { return }
+                    if !matches!(bytes_read, Ok(n) if n > 0) { break; }
+                    // the frame is longer than allowed, or the peer left in the middle of it
+                    if !line.ends_with('\n') { break; }
+                    last_seen = Instant::now();
+                    // nothing a peer sends reaches the app or the room unsanitized
+                    let frame = Frame::decode(&line).map(|mut frame| {
+                        sanitize_frame(&mut frame);
+                        frame
+                    });
+                    match frame {
+                        Some(Frame::Ping { nonce }) => {
+                            let pong = Frame::Pong { nonce }.encode();
+                            if socket_writer.write_all(&pong).await.is_err() { break; }
+                        }
+                        Some(Frame::Pong { nonce }) => {
+                            if let Some((sent_nonce, sent_at)) = pending_ping {
+                                if sent_nonce == nonce {
+                                    pending_ping = None;
+                                    let _ = room.server_app_messages_tx.send(ServerEvent::PeerLatency(peer.clone(), sent_at.elapsed()));
+                                }
+                            }
+                        }
+                        // a user could otherwise pass for another one
+                        Some(Frame::Hello { ref name, .. }) if joined_as.is_none()
+                            && room.relay.as_ref().is_some_and(|relay| relay.is_taken(name, &room.hello)) => {
+                            let reason = format!("the name {} is already taken in this room", name);
+                            let _ = socket_writer.write_all(&Frame::Refused { reason, id: None }.encode()).await;
+                            break;
+                        }
+                        Some(Frame::Hello { name, color, key }) if joined_as.is_none() => {
+                            peer = name.clone();
+                            joined_as = Some((name.clone(), color));
+                            // a peer without a key can only modify what it sent on this link
+                            author = if key.is_empty() { random_id() } else { key };
+                            let _ = room.server_app_messages_tx.send(ServerEvent::PeerJoined(name.clone(), color));
+                            if let Some(relay) = &room.relay {
+                                // introduce the users already in the room to the newcomer
+                                let members = relay.members.lock().unwrap().clone();
+                                for member in members {
+                                    if socket_writer.write_all(&member.encode()).await.is_err() { break; }
+                                }
+                                let hello = Frame::Hello { name, color, key: String::new() };
+                                relay.members.lock().unwrap().push(hello.clone());
+                                let _ = relay.tx.send((id, hello));
+                            }
+                        }
+                        // as a client, hello and leave frames relayed by the host describe the room
+                        Some(Frame::Hello { name, color, .. }) if room.relay.is_none() => {
+                            let _ = room.server_app_messages_tx.send(ServerEvent::PeerJoined(name, color));
+                        }
+                        Some(Frame::Leave { name }) if room.relay.is_none() => {
+                            let _ = room.server_app_messages_tx.send(ServerEvent::PeerLeft(name));
+                        }
+                        Some(Frame::Refused { reason, id: None }) if room.relay.is_none() => {
+                            refused = Some(reason);
+                            break;
+                        }
+                        // the host ignores repeated introductions, spoofed leaves and anonymous frames
+                        Some(Frame::Hello { .. } | Frame::Leave { .. } | Frame::Refused { .. }) => {}
+                        Some(_) if room.relay.is_some() && joined_as.is_none() => {}
+                        // previews are only fetched by the host
+                        Some(Frame::Ack { .. } | Frame::LinkPreview { .. }) if room.relay.is_some() => {}
+                        // a retried message that already reached the room is only acknowledged again,
+                        // a message reusing the id of another one is refused
+                        Some(Frame::Chat(msg)) if room.relay.as_ref().is_some_and(|relay| relay.is_known(&msg.id)) => {
+                            let answer = match room.relay.as_ref().and_then(|relay| relay.stamped(&msg.id, &author)) {
+                                Some(timestamp) => Frame::Ack { id: msg.id, timestamp: Some(timestamp) },
+                                None => Frame::Refused { reason: String::from(ID_TAKEN), id: Some(msg.id) },
+                            };
+                            if socket_writer.write_all(&answer.encode()).await.is_err() { break; }
+                        }
+                        Some(Frame::React { ref emoji, .. }) if room.relay.is_some() && parse_reaction(emoji).is_none() => {}
+                        // only the author of a message may modify it
+                        Some(Frame::Edit { id: msg_id, .. } | Frame::Delete { id: msg_id })
+                            if room.relay.as_ref().is_some_and(|relay| !relay.is_author(&msg_id, &author)) => {
+                            let refused = Frame::Refused { reason: String::from(NOT_AUTHOR), id: Some(msg_id) };
+                            if socket_writer.write_all(&refused.encode()).await.is_err() { break; }
+                        }
+                        Some(mut frame) => {
+                            if let (Some(relay), Some((name, color))) = (&room.relay, &joined_as) {
+                                Self::sign(&mut frame, name, *color);
+                                let _ = relay.tx.send((id, frame.clone()));
+                                let answer = match &frame {
+                                    Frame::Chat(msg) => {
+                                        relay.record(msg, &author);
+                                        Some(Frame::Ack { id: msg.id.clone(), timestamp: Some(msg.timestamp) })
+                                    }
+                                    // the author applies the change once accepted
+                                    Frame::Edit { .. } | Frame::Delete { .. } => Some(frame.clone()),
+                                    _ => None,
+                                };
+                                if let Some(answer) = answer {
+                                    if socket_writer.write_all(&answer.encode()).await.is_err() { break; }
+                                }
+                            }
+                            if room.server_app_messages_tx.send(ServerEvent::Frame(frame)).is_err() { break; }
+                        }
+                        // ignore malformed frames
+                        None => {}
+                    }
                     line.clear();
+                    buff_reader.set_limit(MAX_FRAME as u64);
                 }
-                // user messages
-                result = app_server_messages_rx.recv() => {
-                    let msg = result.unwrap();
-                    if (socket_writer.write_all(&msg.as_bytes()).await).is_err() { return }
+                // frames written by the app or relayed from other peers
+                routed = routed_rx.recv() => match routed {
+                    // the host only hands the frames meant for a user to that user
+                    Ok((_, ref frame)) if room.relay.is_some()
+                        && frame.recipient().is_some_and(|to| Some(to) != joined_as.as_ref().map(|(name, _)| name.as_str())) => {}
+                    Ok((origin, frame)) if origin != id => {
+                        if socket_writer.write_all(&frame.encode()).await.is_err() { break; }
+                        // the messages of the host are delivered once a peer got them
+                        if let (Frame::Chat(msg), true) = (&frame, origin == LOCAL_ID && room.relay.is_some()) {
+                            let ack = Frame::Ack { id: msg.id.clone(), timestamp: None };
+                            let _ = room.server_app_messages_tx.send(ServerEvent::Frame(ack));
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                _ = heartbeat.tick() => {
+                    // the peer vanished without closing the connection
+                    if last_seen.elapsed() > room.config.peer_timeout() { break; }
+                    next_nonce += 1;
+                    pending_ping = Some((next_nonce, Instant::now()));
+                    if socket_writer.write_all(&Frame::Ping { nonce: next_nonce }.encode()).await.is_err() { break; }
                 }
             }
         }
+        if let Some((name, _)) = joined_as {
+            if let Some(relay) = &room.relay {
+                let mut members = relay.members.lock().unwrap();
+                if let Some(pos) = members.iter().position(
+                    |member| matches!(member, Frame::Hello { name: member, .. } if *member == name),
+                ) {
+                    members.remove(pos);
+                }
+                let _ = relay.tx.send((id, Frame::Leave { name: name.clone() }));
+            }
+            let _ = room
+                .server_app_messages_tx
+                .send(ServerEvent::PeerLeft(name));
+        }
+        refused
+    }
+    /// Attributes a frame relayed by the host to the user of the link it came from,
+    /// so a peer cannot speak on behalf of someone else.
+    /// A new message starts unedited and without reactions, whatever the peer claims.
+    fn sign(frame: &mut Frame, author: &str, color: Color) {
+        match frame {
+            Frame::Chat(msg) => {
+                msg.source = author.to_owned();
+                msg.color = color;
+                // the host's clock is the reference for the whole room
+                msg.timestamp = Utc::now().timestamp_millis();
+                msg.edited = false;
+                msg.deleted = false;
+                msg.reactions.clear();
+            }
+            Frame::Typing { name, .. }
+            | Frame::React { name, .. }
+            | Frame::FileOffer { name, .. }
+            | Frame::FileAnswer { name, .. }
+            | Frame::FileChunk { name, .. }
+            | Frame::FileAck { name, .. } => *name = author.to_owned(),
+            _ => {}
+        }
+    }
+    /// Tags the frames written by the app so they can be routed to the peers.
+    /// When hosting, the messages of the host are recorded as relayed and its edits and
+    /// deletions are checked like those of the peers, then handed back to the app.
+    async fn forward_app_frames(
+        mut app_server_messages_rx: broadcast::Receiver<Frame>,
+        routed_tx: broadcast::Sender<Routed>,
+        room: RoomContext,
+    ) {
+        loop {
+            match app_server_messages_rx.recv().await {
+                Ok(frame) => {
+                    let answer = match (&room.relay, &frame) {
+                        (Some(relay), Frame::Chat(msg)) => {
+                            relay.record(msg, &room.key);
+                            None
+                        }
+                        (Some(relay), Frame::Edit { id, .. } | Frame::Delete { id }) => {
+                            if !relay.is_author(id, &room.key) {
+                                let reason = String::from(NOT_AUTHOR);
+                                let refused = Frame::Refused {
+                                    reason,
+                                    id: Some(id.clone()),
+                                };
+                                let _ = room
+                                    .server_app_messages_tx
+                                    .send(ServerEvent::Frame(refused));
+                                continue;
+                            }
+                            Some(frame.clone())
+                        }
+                        _ => None,
+                    };
+                    let _ = routed_tx.send((LOCAL_ID, frame));
+                    if let Some(answer) = answer {
+                        let _ = room.server_app_messages_tx.send(ServerEvent::Frame(answer));
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
     }
     async fn run(
         &mut self,
-        server_app_messages_tx: broadcast::Sender<Message>,
-        app_server_messages_tx: broadcast::Sender<Message>,
+        announced: bool,
+        server_app_messages_tx: broadcast::Sender<ServerEvent>,
+        app_server_messages_tx: broadcast::Sender<Frame>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // wait for incoming connections
-        let listener: TcpListener = TcpListener::bind(self.session_link.clone()).await?;
+        let bind = self.config.bind_address(announced);
+        let listener: TcpListener = match TcpListener::bind(&bind).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                let _ = server_app_messages_tx.send(ServerEvent::Disconnected(format!(
+                    "could not host on {}: {}",
+                    bind, e
+                )));
+                return Err(e.into());
+            }
+        };
+        // the hosted room is known locally by its port, whatever interfaces it listens on
+        let port = listener.local_addr().map_or(0, |addr| addr.port());
+        self.session_link = format!("localhost:{}", port);
+        let _ = server_app_messages_tx.send(ServerEvent::Connected(self.session_link.clone()));
+        let (routed_tx, _) = broadcast::channel::<Routed>(64);
+        let members = Arc::new(Mutex::new(vec![]));
+        let announcement = announced.then(|| {
+            let room = self
+                .config
+                .room_name
+                .clone()
+                .unwrap_or_else(|| format!("{}'s room", self.profile.name));
+            Announcement::new(self.profile.name.clone(), room, port)
+        });
+        let relay = Relay {
+            tx: routed_tx.clone(),
+            members: members.clone(),
+            relayed: Arc::new(Mutex::new(RelayedMessages::default())),
+        };
+        let room = self.room_context(server_app_messages_tx, Some(relay.clone()));
+        // dropping the set when the room closes aborts every client task
+        let mut clients = JoinSet::new();
+        let mut next_id = LOCAL_ID;
 
-        // TODO: set a limit on the number of clients able to connect
-        while let Ok((socket, _)) = listener.accept().await {
-            // dispatch a task for each new client
-            tokio::spawn(Self::handle_client(
-                socket,
-                server_app_messages_tx.clone(),
-                app_server_messages_tx.clone(),
-            ));
+        let accept_clients = async {
+            // TODO: set a limit on the number of clients able to connect
+            while let Ok((socket, addr)) = listener.accept().await {
+                next_id += 1;
+                // dispatch a task for each new client
+                clients.spawn(Self::handle_client(
+                    socket,
+                    addr.to_string(),
+                    next_id,
+                    routed_tx.subscribe(),
+                    room.clone(),
+                ));
+            }
+        };
+        tokio::select! {
+            _ = accept_clients => {}
+            _ = Self::forward_app_frames(app_server_messages_tx.subscribe(), routed_tx.clone(), room.clone()) => {}
+            _ = Self::announce_room(announcement, members) => {}
         }
         Ok(())
     }
+    /// Announces the room on the local network when enabled, never returns
+    async fn announce_room(announcement: Option<Announcement>, members: Arc<Mutex<Vec<Frame>>>) {
+        if let Some(announcement) = announcement {
+            // the room works without announcements, failing to send them is not fatal
+            let _ = announce(announcement, || members.lock().unwrap().len() + 1).await;
+        }
+        std::future::pending().await
+    }
     pub async fn start(
         &mut self,
         mut commands_channel: broadcast::Receiver<ServerCommand>,
@@ -96,12 +551,13 @@ impl Server {
                     }
                 }
                 ServerCommand::HostRoom((
+                    announced,
                     mut exit_signal,
                     server_app_messages_tx,
                     app_server_messages_tx,
                 )) => {
                     tokio::select! {
-                        _ = self.run(server_app_messages_tx, app_server_messages_tx) => {}
+                        _ = self.run(announced, server_app_messages_tx, app_server_messages_tx) => {}
                         _ = exit_signal.changed() => {}
                     }
                 }
@@ -109,3 +565,289 @@ impl Server {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+        task::JoinHandle,
+    };
+
+    use super::*;
+    use crate::models::message::Reaction;
+
+    /// The peer's end of a link to a hosted room
+    struct Peer {
+        reader: BufReader<OwnedReadHalf>,
+        writer: OwnedWriteHalf,
+        /// what the link relays to the rest of the room
+        relayed: broadcast::Receiver<Routed>,
+        /// what the link hands to the app of the host, the link closes without it
+        _events: broadcast::Receiver<ServerEvent>,
+        link: JoinHandle<Option<String>>,
+    }
+
+    impl Peer {
+        async fn send(&mut self, frame: Frame) {
+            self.writer.write_all(&frame.encode()).await.unwrap();
+        }
+        /// The next frame written to the peer, pings and introductions excluded
+        async fn next(&mut self) -> Frame {
+            loop {
+                let mut line = String::new();
+                let read = self.reader.read_line(&mut line);
+                time::timeout(Duration::from_secs(5), read)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                match Frame::decode(&line).unwrap() {
+                    Frame::Ping { .. } | Frame::Hello { .. } | Frame::Leave { .. } => {}
+                    frame => return frame,
+                }
+            }
+        }
+        /// The next frame relayed to the room
+        async fn next_relayed(&mut self) -> Frame {
+            let relayed = time::timeout(Duration::from_secs(5), self.relayed.recv());
+            relayed.await.unwrap().unwrap().1
+        }
+    }
+
+    fn relay() -> Relay {
+        Relay {
+            tx: broadcast::channel(64).0,
+            members: Arc::new(Mutex::new(vec![])),
+            relayed: Arc::new(Mutex::new(RelayedMessages::default())),
+        }
+    }
+
+    /// Opens the link `id` of the room and introduces the peer as `name`
+    async fn join(relay: &Relay, id: usize, name: &str, key: &str, config: NetworkConfig) -> Peer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket = TcpStream::connect(listener.local_addr().unwrap());
+        let (socket, accepted) = tokio::join!(socket, listener.accept());
+        let (accepted, addr) = accepted.unwrap();
+        let host = ProfileConfig {
+            name: String::from("host"),
+            ..ProfileConfig::default()
+        };
+        let (events_tx, events) = broadcast::channel(64);
+        let room = Server::new(config, host).room_context(events_tx, Some(relay.clone()));
+        let relayed = relay.tx.subscribe();
+        let link = tokio::spawn(Server::handle_client(
+            accepted,
+            addr.to_string(),
+            id,
+            relay.tx.subscribe(),
+            room,
+        ));
+        let (reader, writer) = socket.unwrap().into_split();
+        let mut peer = Peer {
+            reader: BufReader::new(reader),
+            writer,
+            relayed,
+            _events: events,
+            link,
+        };
+        let hello = Frame::Hello {
+            name: name.to_owned(),
+            color: Color::Red,
+            key: key.to_owned(),
+        };
+        peer.send(hello).await;
+        assert!(matches!(peer.next_relayed().await, Frame::Hello { .. }));
+        peer
+    }
+
+    #[tokio::test]
+    async fn relays_messages_without_the_state_claimed_by_the_peer() {
+        let relay = relay();
+        let mut eve = join(&relay, 1, "eve", "", NetworkConfig::default()).await;
+        let mut msg = Message::new(String::from("hi"), Color::Blue, String::from("alice"));
+        msg.edited = true;
+        msg.deleted = true;
+        msg.reactions.push(Reaction {
+            emoji: String::from("<script>"),
+            users: vec![String::from("alice")],
+        });
+        eve.send(Frame::Chat(msg)).await;
+        let Frame::Chat(relayed) = eve.next_relayed().await else {
+            panic!("the message was not relayed")
+        };
+        assert_eq!(
+            (relayed.source.as_str(), relayed.color),
+            ("eve", Color::Red)
+        );
+        assert!(relayed.reactions.is_empty());
+        assert!(!relayed.edited && !relayed.deleted);
+        assert!(matches!(eve.next().await, Frame::Ack { .. }));
+        eve.link.abort();
+    }
+
+    /// Sends a message as the peer, returns its id once acknowledged
+    async fn chat(peer: &mut Peer) -> String {
+        let msg = Message::new(String::from("hi"), Color::Red, String::new());
+        peer.send(Frame::Chat(msg.clone())).await;
+        assert!(matches!(peer.next().await, Frame::Ack { id, .. } if id == msg.id));
+        peer.next_relayed().await;
+        msg.id
+    }
+
+    fn edit(id: &str) -> Frame {
+        Frame::Edit {
+            id: id.to_owned(),
+            content: String::from("edited"),
+            suspicious: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn hands_accepted_edits_back_to_their_author() {
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "alice's key", NetworkConfig::default()).await;
+        let id = chat(&mut alice).await;
+        alice.send(edit(&id)).await;
+        let relayed = alice.next_relayed().await;
+        assert!(matches!(relayed, Frame::Edit { id: edited, .. } if edited == id));
+        assert!(matches!(alice.next().await, Frame::Edit { id: edited, .. } if edited == id));
+        alice.send(Frame::Delete { id: id.clone() }).await;
+        assert!(matches!(alice.next().await, Frame::Delete { id: deleted } if deleted == id));
+        alice.link.abort();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_modify_the_messages_of_others() {
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "alice's key", NetworkConfig::default()).await;
+        let id = chat(&mut alice).await;
+        let mut eve = join(&relay, 2, "eve", "eve's key", NetworkConfig::default()).await;
+        for frame in [edit(&id), Frame::Delete { id: id.clone() }] {
+            eve.send(frame).await;
+            let refused = eve.next().await;
+            assert!(matches!(refused, Frame::Refused { id: Some(refused), .. } if refused == id));
+        }
+        assert!(eve.relayed.try_recv().is_err());
+        alice.link.abort();
+        eve.link.abort();
+    }
+
+    #[tokio::test]
+    async fn acknowledges_retried_messages_only_to_their_author() {
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "alice's key", NetworkConfig::default()).await;
+        let msg = Message::new(String::from("hi"), Color::Red, String::new());
+        alice.send(Frame::Chat(msg.clone())).await;
+        let Frame::Ack { timestamp, .. } = alice.next().await else {
+            panic!("the message was not acknowledged")
+        };
+        alice.send(Frame::Chat(msg.clone())).await;
+        assert!(
+            matches!(alice.next().await, Frame::Ack { timestamp: again, .. } if again == timestamp)
+        );
+        let mut eve = join(&relay, 2, "eve", "eve's key", NetworkConfig::default()).await;
+        eve.send(Frame::Chat(msg.clone())).await;
+        assert!(matches!(eve.next().await, Frame::Refused { id: Some(id), .. } if id == msg.id));
+        assert!(eve.relayed.try_recv().is_err());
+        alice.link.abort();
+        eve.link.abort();
+    }
+
+    #[tokio::test]
+    async fn recognizes_authors_once_reconnected() {
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "alice's key", NetworkConfig::default()).await;
+        let id = chat(&mut alice).await;
+        drop(alice.writer);
+        assert_eq!(alice.link.await.unwrap(), None);
+        let mut alice = join(&relay, 2, "alice", "alice's key", NetworkConfig::default()).await;
+        alice.send(edit(&id)).await;
+        assert!(matches!(alice.next().await, Frame::Edit { .. }));
+        // the name alone is not enough
+        let mut impostor = join(&relay, 3, "bob", "", NetworkConfig::default()).await;
+        impostor.send(edit(&id)).await;
+        assert!(matches!(impostor.next().await, Frame::Refused { .. }));
+        alice.link.abort();
+        impostor.link.abort();
+    }
+
+    #[tokio::test]
+    async fn answers_pings() {
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "", NetworkConfig::default()).await;
+        alice.send(Frame::Ping { nonce: 7 }).await;
+        assert!(matches!(alice.next().await, Frame::Pong { nonce: 7 }));
+        alice.link.abort();
+    }
+
+    #[tokio::test]
+    async fn drops_peers_that_stop_answering() {
+        let config = NetworkConfig {
+            ping_interval_secs: 1,
+            peer_timeout_secs: 2,
+            ..NetworkConfig::default()
+        };
+        let relay = relay();
+        let mut alice = join(&relay, 1, "alice", "", config).await;
+        // the pings are read but never answered
+        let closed = time::timeout(Duration::from_secs(5), &mut alice.link).await;
+        assert_eq!(closed.unwrap().unwrap(), None);
+        assert!(matches!(alice.next_relayed().await, Frame::Leave { name } if name == "alice"));
+    }
+
+    #[test]
+    fn forgets_the_oldest_messages() {
+        let mut relayed = RelayedMessages::default();
+        let msg = || Relayed {
+            author: String::from("key"),
+            timestamp: 0,
+        };
+        for i in 0..=MAX_RELAYED {
+            relayed.insert(i.to_string(), msg());
+        }
+        // recording a message again does not make it newer
+        relayed.insert(String::from("1"), msg());
+        assert!(relayed.get("0").is_none());
+        assert!(relayed.get("1").is_some());
+        assert!(relayed.get(&MAX_RELAYED.to_string()).is_some());
+        assert_eq!(relayed.by_id.len(), MAX_RELAYED);
+    }
+
+    #[tokio::test]
+    async fn reconnects_to_a_lost_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let link = listener.local_addr().unwrap().to_string();
+        let config = NetworkConfig {
+            reconnect_attempts: 1,
+            reconnect_delay_secs: 0,
+            ..NetworkConfig::default()
+        };
+        let mut client = Server::new(config, ProfileConfig::default());
+        let (events_tx, mut events) = broadcast::channel(64);
+        let joined = client.join(link.clone(), events_tx, broadcast::channel(64).0);
+        let host = async {
+            drop(listener.accept().await.unwrap());
+            // the host is gone for good once the second link is lost
+            let second = listener.accept().await.unwrap();
+            drop(listener);
+            drop(second);
+        };
+        let (joined, _) = tokio::join!(joined, host);
+        assert!(joined.is_ok());
+        let mut received = vec![];
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert!(matches!(
+            received.as_slice(),
+            [
+                ServerEvent::Connected(_),
+                ServerEvent::Reconnecting(1),
+                ServerEvent::Connected(_),
+                ServerEvent::Reconnecting(1),
+                ServerEvent::Disconnected(_),
+            ]
+        ));
+    }
+}
@@ -1,21 +1,23 @@
-use crate::models::message::Message;
+use super::{protocol::Frame, server_events::ServerEvent};
 use tokio::sync::{broadcast, watch};
 
 #[derive(Debug, Clone)]
 pub enum ServerCommand {
+    /// whether the room is announced on the local network, along with the channels of the room
     HostRoom(
         (
+            bool,
             watch::Receiver<bool>,
-            broadcast::Sender<Message>,
-            broadcast::Sender<Message>,
+            broadcast::Sender<ServerEvent>,
+            broadcast::Sender<Frame>,
         ),
     ),
     JoinRoom(
         (
             String,
             watch::Receiver<bool>,
-            broadcast::Sender<Message>,
-            broadcast::Sender<Message>,
+            broadcast::Sender<ServerEvent>,
+            broadcast::Sender<Frame>,
         ),
     ),
 }
@@ -0,0 +1,21 @@
+use ratatui::style::Color;
+use std::time::Duration;
+
+use super::protocol::Frame;
+
+/// Events sent from the server to the app
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    /// a frame received from the room
+    Frame(Frame),
+    /// the room behind the link is reachable, or is being hosted
+    Connected(String),
+    PeerJoined(String, Color),
+    PeerLeft(String),
+    /// measured round-trip time to a peer
+    PeerLatency(String, Duration),
+    /// the host went away, attempt number is attached
+    Reconnecting(u32),
+    /// the host could not be reached anymore
+    Disconnected(String),
+}
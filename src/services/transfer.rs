@@ -0,0 +1,238 @@
+use std::{io::SeekFrom, path::PathBuf, time::Duration};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::{broadcast, mpsc},
+    time,
+};
+
+use super::protocol::Frame;
+
+/// bytes of a file sent in one frame
+const CHUNK_SIZE: usize = 16 * 1024;
+/// pause between two chunks, so chat frames keep flowing
+const CHUNK_INTERVAL: Duration = Duration::from_millis(10);
+/// chunks sent ahead of the last one acknowledged, few enough for the channels not to overflow
+const WINDOW: u64 = 8;
+/// the chunks not acknowledged by then are sent again, from the last byte acknowledged
+const RESEND_AFTER: Duration = Duration::from_secs(2);
+/// times the chunks are sent again without progress before giving up
+const MAX_RESENDS: u32 = 5;
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Progress of a file being sent, reported to the app
+#[derive(Debug, Clone)]
+pub enum TransferUpdate {
+    /// bytes sent so far to the peer
+    Sent { id: String, peer: String, sent: u64 },
+    Failed {
+        id: String,
+        peer: String,
+        reason: String,
+    },
+}
+
+/// What sending a file to a peer needs to know
+pub struct Upload {
+    pub id: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// name of the local user, the author of the chunks
+    pub name: String,
+    pub peer: String,
+}
+
+/// Sends a file to a peer chunk by chunk, reporting the progress along the way.
+/// A few chunks are sent ahead of the bytes the peer acknowledged, the ones lost on the way
+/// are sent again once the acknowledgements stop coming.
+pub async fn send_file(
+    upload: Upload,
+    frames_tx: broadcast::Sender<Frame>,
+    updates_tx: mpsc::UnboundedSender<TransferUpdate>,
+    mut acks_rx: mpsc::UnboundedReceiver<u64>,
+) {
+    let failed = |reason: String| TransferUpdate::Failed {
+        id: upload.id.clone(),
+        peer: upload.peer.clone(),
+        reason,
+    };
+    let mut file = match File::open(&upload.path).await {
+        Ok(file) => file,
+        Err(e) => {
+            let _ = updates_tx.send(failed(e.to_string()));
+            return;
+        }
+    };
+    let mut buffer = vec![0; CHUNK_SIZE];
+    // next byte to send, and bytes received by the peer
+    let mut offset = 0;
+    let mut acked = 0;
+    let mut resends = 0;
+    // everything is sent, or too much is waiting for an acknowledgement
+    let blocked = |offset: u64, acked: u64| {
+        offset >= upload.size || offset - acked >= WINDOW * CHUNK_SIZE as u64
+    };
+    loop {
+        let mut received = None;
+        while let Ok(bytes) = acks_rx.try_recv() {
+            received = received.max(Some(bytes));
+        }
+        if received.is_none() && blocked(offset, acked) {
+            match time::timeout(RESEND_AFTER, acks_rx.recv()).await {
+                Ok(Some(bytes)) => received = Some(bytes),
+                // the app stopped following the transfer
+                Ok(None) => return,
+                Err(_) if resends == MAX_RESENDS => {
+                    let _ = updates_tx.send(failed(String::from("the peer stopped answering")));
+                    return;
+                }
+                Err(_) => {
+                    resends += 1;
+                    offset = acked;
+                }
+            }
+        }
+        // the acknowledgements of the chunks sent before going back may come late
+        if let Some(bytes) = received.filter(|bytes| *bytes > acked && *bytes <= upload.size) {
+            acked = bytes;
+            offset = offset.max(acked);
+            resends = 0;
+            let _ = updates_tx.send(TransferUpdate::Sent {
+                id: upload.id.clone(),
+                peer: upload.peer.clone(),
+                sent: acked,
+            });
+        }
+        if acked >= upload.size {
+            return;
+        }
+        if blocked(offset, acked) {
+            continue;
+        }
+        let read = match file.seek(SeekFrom::Start(offset)).await {
+            Ok(_) => file.read(&mut buffer).await,
+            Err(e) => Err(e),
+        };
+        let len = match read {
+            Ok(0) => {
+                let _ = updates_tx.send(failed(String::from("the file got shorter")));
+                return;
+            }
+            Ok(len) => len,
+            Err(e) => {
+                let _ = updates_tx.send(failed(e.to_string()));
+                return;
+            }
+        };
+        let chunk = &buffer[..len];
+        let frame = Frame::FileChunk {
+            id: upload.id.clone(),
+            name: upload.name.clone(),
+            to: upload.peer.clone(),
+            offset,
+            data: encode_base64(chunk),
+            checksum: checksum(chunk),
+        };
+        if frames_tx.send(frame).is_err() {
+            let _ = updates_tx.send(failed(String::from("the room was closed")));
+            return;
+        }
+        offset += len as u64;
+        time::sleep(CHUNK_INTERVAL).await;
+    }
+}
+
+/// FNV-1a of a chunk, catches the chunks damaged on the way
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let b = [
+            group[0],
+            *group.get(1).unwrap_or(&0),
+            *group.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// None when the text is not valid base64
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    // the padding only completes the last group
+    let unpadded = text.trim_end_matches('=');
+    let padding = text.len() - unpadded.len();
+    if padding > 2 || (padding > 0 && !text.len().is_multiple_of(4)) {
+        return None;
+    }
+    let text = unpadded.as_bytes();
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    for group in text.chunks(4) {
+        if group.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in group.iter().enumerate() {
+            let value = BASE64.iter().position(|b| b == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..group.len() - 1 {
+            decoded.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode_base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn decodes_base64_back() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for len in 0..bytes.len() {
+            let encoded = encode_base64(&bytes[..len]);
+            assert_eq!(decode_base64(&encoded).as_deref(), Some(&bytes[..len]));
+        }
+        assert_eq!(decode_base64("Zm8").as_deref(), Some(&b"fo"[..]));
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        assert_eq!(decode_base64("Z"), None);
+        assert_eq!(decode_base64("Zg=a"), None);
+        assert_eq!(decode_base64("Zm9v!"), None);
+        assert_eq!(decode_base64("Zg==="), None);
+        assert_eq!(decode_base64("Zg="), None);
+        assert_eq!(decode_base64("Zm 9v"), None);
+    }
+
+    #[test]
+    fn checksums_with_fnv1a() {
+        assert_eq!(checksum(b""), 0xcbf29ce484222325);
+        assert_eq!(checksum(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(checksum(b"foobar"), 0x85944171f73967e8);
+    }
+}
@@ -0,0 +1,101 @@
+use ratatui::style::Color;
+
+/// Colors handed out to users who did not choose one.
+/// Only named colors are used so they render the same on 16-color and truecolor terminals.
+const USER_PALETTE: [Color; 10] = [
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Cyan,
+];
+/// relative luminance under which a color is too dark to be read on the background
+const MIN_LUMINANCE: f32 = 0.25;
+
+/// The color of a user, their chosen one if any, otherwise one derived from a stable id
+pub fn user_color(stable_id: &str, chosen: Option<Color>) -> Color {
+    match chosen {
+        Some(color) => readable(color),
+        None => USER_PALETTE[(fnv1a(stable_id.as_bytes()) % USER_PALETTE.len() as u64) as usize],
+    }
+}
+
+/// Adjusts a color so it stays readable on a dark background and can be shown by the terminal
+pub fn readable(color: Color) -> Color {
+    match color {
+        Color::Black | Color::Reset => Color::Gray,
+        Color::DarkGray => Color::Gray,
+        Color::Blue => Color::LightBlue,
+        Color::Rgb(r, g, b) => {
+            let (r, g, b) = lighten((r, g, b));
+            if supports_truecolor() {
+                Color::Rgb(r, g, b)
+            } else {
+                nearest_ansi((r, g, b))
+            }
+        }
+        color => color,
+    }
+}
+
+/// Whether the terminal advertises 24 bit colors
+pub fn supports_truecolor() -> bool {
+    std::env::var("COLORTERM")
+        .map(|value| value == "truecolor" || value == "24bit")
+        .unwrap_or(false)
+}
+
+fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0
+}
+
+/// Mixes a dark color with white until it reaches the minimum luminance
+fn lighten(mut rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+    while luminance(rgb) < MIN_LUMINANCE {
+        let mix = |c: u8| c + (255 - c) / 4;
+        rgb = (mix(rgb.0), mix(rgb.1), mix(rgb.2));
+    }
+    rgb
+}
+
+/// The closest of the 16 standard terminal colors
+pub fn nearest_ansi(rgb: (u8, u8, u8)) -> Color {
+    const ANSI: [(Color, (u8, u8, u8)); 16] = [
+        (Color::Black, (0, 0, 0)),
+        (Color::Red, (205, 0, 0)),
+        (Color::Green, (0, 205, 0)),
+        (Color::Yellow, (205, 205, 0)),
+        (Color::Blue, (0, 0, 238)),
+        (Color::Magenta, (205, 0, 205)),
+        (Color::Cyan, (0, 205, 205)),
+        (Color::Gray, (229, 229, 229)),
+        (Color::DarkGray, (127, 127, 127)),
+        (Color::LightRed, (255, 0, 0)),
+        (Color::LightGreen, (0, 255, 0)),
+        (Color::LightYellow, (255, 255, 0)),
+        (Color::LightBlue, (92, 92, 255)),
+        (Color::LightMagenta, (255, 0, 255)),
+        (Color::LightCyan, (0, 255, 255)),
+        (Color::White, (255, 255, 255)),
+    ];
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    ANSI.iter()
+        .min_by_key(|(_, candidate)| distance(*candidate))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+/// FNV-1a, stable across runs and platforms unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
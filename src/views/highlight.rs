@@ -0,0 +1,123 @@
+use ratatui::style::Color;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+use syntect::{
+    easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet, util::LinesWithEndings,
+};
+
+use super::colors::{nearest_ansi, supports_truecolor};
+
+/// code theme that leaves the code uncolored
+pub const NO_HIGHLIGHTING: &str = "none";
+/// columns a tab is expanded to
+const TAB_WIDTH: usize = 4;
+/// code blocks kept highlighted, far more than a pane shows at once
+const CACHE_CAPACITY: usize = 128;
+
+/// A line of code split into colored pieces
+pub type CodeLine = Vec<(Color, String)>;
+
+/// Lines of code already highlighted, by code theme, language and code.
+/// Messages are composed on every frame, highlighting them each time would be far too slow.
+/// The blocks used the least recently are dropped, peers could send them without end.
+#[derive(Default)]
+struct Cache {
+    /// the lines, along with the last use of the block
+    entries: HashMap<(String, String, String), (u64, Vec<CodeLine>)>,
+    uses: u64,
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the code themes that can be used in the theme files
+pub fn code_themes() -> Vec<&'static str> {
+    let mut names = themes()
+        .themes
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    names.push(NO_HIGHLIGHTING);
+    names
+}
+
+/// Colors the code of a language, e.g. "rust" or "py", with a code theme.
+/// Code of an unknown language or in the "none" theme keeps the default color.
+pub fn highlight(code_theme: &str, lang: &str, code: &str) -> Vec<CodeLine> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    let key = (code_theme.to_owned(), lang.to_owned(), code.to_owned());
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    cache.uses += 1;
+    let now = cache.uses;
+    if let Some((used, lines)) = cache.entries.get_mut(&key) {
+        *used = now;
+        return lines.clone();
+    }
+    if cache.entries.len() >= CACHE_CAPACITY {
+        let oldest = cache
+            .entries
+            .iter()
+            .min_by_key(|(_, (used, _))| *used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            cache.entries.remove(&oldest);
+        }
+    }
+    let lines = highlight_uncached(code_theme, lang, code);
+    cache.entries.insert(key, (now, lines.clone()));
+    lines
+}
+
+fn highlight_uncached(code_theme: &str, lang: &str, code: &str) -> Vec<CodeLine> {
+    let code = code.replace('\t', &" ".repeat(TAB_WIDTH));
+    let plain = || {
+        code.split('\n')
+            .map(|line| vec![(Color::Reset, line.to_owned())])
+            .collect()
+    };
+    let (Some(syntax), Some(theme)) = (
+        syntaxes().find_syntax_by_token(lang),
+        themes().themes.get(code_theme),
+    ) else {
+        return plain();
+    };
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut lines = vec![];
+    for line in LinesWithEndings::from(&code) {
+        let Ok(pieces) = highlighter.highlight_line(line, syntaxes()) else {
+            return plain();
+        };
+        lines.push(
+            pieces
+                .into_iter()
+                .map(|(style, text)| {
+                    let rgb = (style.foreground.r, style.foreground.g, style.foreground.b);
+                    (fit(rgb), text.trim_end_matches('\n').to_owned())
+                })
+                .filter(|(_, text)| !text.is_empty())
+                .collect(),
+        );
+    }
+    // the line following a final new line is not yielded
+    if code.is_empty() || code.ends_with('\n') {
+        lines.push(vec![]);
+    }
+    lines
+}
+
+fn fit(rgb: (u8, u8, u8)) -> Color {
+    if supports_truecolor() {
+        Color::Rgb(rgb.0, rgb.1, rgb.2)
+    } else {
+        nearest_ansi(rgb)
+    }
+}
@@ -0,0 +1,511 @@
+use super::{highlight::highlight, theme::Theme};
+use crate::models::{
+    config::TimestampFormat,
+    markdown::{blocks, inlines, quote, Block, Format, Inline},
+    mentions::{find_substrings, find_terms},
+    message::{DeliveryStatus, LinkPreview, Message},
+};
+use crate::services::sanitize::sanitize;
+use chrono::{Local, NaiveDate};
+use ratatui::{
+    layout::Alignment,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+use unicode_width::UnicodeWidthChar;
+
+/// characters of the parent message quoted above an answer
+const QUOTE_LENGTH: usize = 60;
+/// narrowest code shown in a code block, narrower panes wrap the block
+const MIN_CODE_WIDTH: usize = 8;
+
+/// What the messages are composed with
+pub struct ComposeOptions<'a> {
+    pub theme: &'a Theme,
+    /// name of the root user, whose reactions stand out
+    pub me: &'a str,
+    /// terms standing out in the messages that mention them
+    pub highlights: &'a [String],
+    /// terms searched for, standing out wherever they are found
+    pub search: &'a [String],
+    pub timestamps: TimestampFormat,
+    pub selected: Option<usize>,
+    /// ids of the messages of the thread or of the search results, the only ones composed when given
+    pub thread: Option<&'a HashSet<String>>,
+    /// columns of the message pane, code blocks do not wrap and are clipped to it
+    pub width: usize,
+    /// columns the code blocks of the selected message are scrolled by
+    pub code_scroll: usize,
+    /// show every message as raw text, or only the ones of these users
+    pub raw: bool,
+    pub raw_users: &'a HashSet<String>,
+}
+
+/// Composes the messages to be rendered, separating the days.
+/// The range of lines used by each message is returned along with the lines,
+/// and the maximum scroll of the code blocks of the selected message.
+pub fn compose_msgs<'a>(
+    messages: &[Message],
+    options: &ComposeOptions,
+) -> (Vec<Line<'a>>, Vec<Range<usize>>, usize) {
+    let ComposeOptions {
+        theme,
+        me,
+        selected,
+        thread,
+        ..
+    } = *options;
+    let by_id = messages
+        .iter()
+        .filter(|msg| !msg.is_notice())
+        .map(|msg| (msg.id.as_str(), msg))
+        .collect::<HashMap<_, _>>();
+    let mut lines = Vec::with_capacity(messages.len());
+    let mut ranges = Vec::with_capacity(messages.len());
+    let mut last_day = None;
+    let mut max_code_scroll = 0;
+    for (i, msg) in messages.iter().enumerate() {
+        if thread.is_some_and(|ids| !ids.contains(&msg.id)) {
+            ranges.push(lines.len()..lines.len());
+            continue;
+        }
+        let day = msg.local_time().date_naive();
+        if last_day != Some(day) {
+            lines.push(day_separator(theme, day));
+            last_day = Some(day);
+        }
+        let start = lines.len();
+        if let Some(parent) = &msg.reply_to {
+            lines.push(compose_quote(theme, by_id.get(parent.as_str()).copied()));
+        }
+        let code_scroll = if selected == Some(i) {
+            options.code_scroll
+        } else {
+            0
+        };
+        let (msg_lines, max_scroll) = compose_msg(options, msg, code_scroll);
+        lines.extend(msg_lines);
+        if !msg.deleted {
+            lines.extend(
+                msg.previews
+                    .iter()
+                    .map(|preview| compose_preview(theme, preview)),
+            );
+        }
+        if !msg.reactions.is_empty() && !msg.deleted {
+            lines.push(compose_reactions(theme, msg, me));
+        }
+        if selected == Some(i) {
+            max_code_scroll = max_scroll;
+            for line in &mut lines[start..] {
+                line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+        }
+        ranges.push(start..lines.len());
+    }
+    (lines, ranges, max_code_scroll)
+}
+/// Aggregated reactions shown under a message, the ones of the root user stand out
+fn compose_reactions<'a>(theme: &Theme, msg: &Message, me: &str) -> Line<'a> {
+    let mut spans = vec![Span::raw("       ")];
+    for reaction in &msg.reactions {
+        let style = if reaction.users.iter().any(|user| user == me) {
+            Style::default().fg(theme.own_reaction)
+        } else {
+            Style::default().fg(theme.muted)
+        };
+        spans.push(Span::styled(
+            format!(" {} {} ", reaction.emoji, reaction.users.len()),
+            style,
+        ));
+    }
+    Line::from(spans)
+}
+/// e.g. "↳ Page title — what the page is about", shown under the message linking to the page
+fn compose_preview<'a>(theme: &Theme, preview: &LinkPreview) -> Line<'a> {
+    let style = Style::default().fg(theme.muted);
+    let mut spans = vec![
+        Span::styled("       ↳ ", style),
+        Span::styled(preview.title.clone(), style.add_modifier(Modifier::BOLD)),
+    ];
+    if let Some(description) = &preview.description {
+        spans.push(Span::styled(format!(" — {}", description), style));
+    }
+    Line::from(spans)
+}
+/// Snippet of the message being answered, shown above the answer
+fn compose_quote<'a>(theme: &Theme, parent: Option<&Message>) -> Line<'a> {
+    let style = Style::default().fg(theme.muted);
+    match parent {
+        Some(parent) => Line::from(vec![
+            Span::styled("       ╭ ", style),
+            Span::styled(
+                format!("{} ", parent.source),
+                style
+                    .fg(theme.user(parent.color))
+                    .add_modifier(Modifier::DIM),
+            ),
+            Span::styled(parent.snippet(QUOTE_LENGTH), style),
+        ]),
+        None => Line::styled("       ╭ [unknown message]", style),
+    }
+}
+/// e.g. "— Tuesday 14 Oct —"
+fn day_separator<'a>(theme: &Theme, day: NaiveDate) -> Line<'a> {
+    Line::styled(
+        format!("— {} —", day.format("%A %d %b")),
+        Style::default().fg(theme.muted),
+    )
+    .alignment(Alignment::Center)
+}
+fn format_timestamp(msg: &Message, format: TimestampFormat) -> Option<String> {
+    match format {
+        TimestampFormat::Clock => Some(msg.local_time().format("%H:%M").to_string()),
+        TimestampFormat::Relative => {
+            let elapsed = (Local::now() - msg.local_time()).num_seconds().max(0);
+            Some(match elapsed {
+                0..=59 => String::from("now"),
+                60..=3599 => format!("{}m", elapsed / 60),
+                3600..=86399 => format!("{}h", elapsed / 3600),
+                _ => format!("{}d", elapsed / 86400),
+            })
+        }
+        TimestampFormat::Hidden => None,
+    }
+}
+/// Composes a user message to be rendered, its code blocks scrolled by the given columns.
+/// Returns the lines along with the maximum scroll of the code blocks.
+fn compose_msg<'a>(
+    options: &ComposeOptions,
+    msg: &Message,
+    code_scroll: usize,
+) -> (Vec<Line<'a>>, usize) {
+    let theme = options.theme;
+    let mut spans = vec![];
+    if let Some(time) = format_timestamp(msg, options.timestamps) {
+        spans.push(Span::styled(
+            format!(" {:>5}", time),
+            Style::default().fg(theme.muted),
+        ));
+    }
+    let color = if msg.is_notice() {
+        theme.muted
+    } else {
+        theme.user(msg.color)
+    };
+    spans.push(Span::styled(
+        format!(" <{}>", msg.source),
+        Style::default().add_modifier(Modifier::BOLD).fg(color),
+    ));
+    spans.push(if msg.suspicious {
+        Span::styled(" ⚠ ", Style::default().fg(theme.failed))
+    } else if msg.highlighted {
+        Span::styled(" ▸ ", Style::default().fg(theme.highlight))
+    } else {
+        Span::raw("  ")
+    });
+    if msg.deleted {
+        spans.push(Span::styled(
+            "[deleted]",
+            Style::default()
+                .fg(theme.muted)
+                .add_modifier(Modifier::ITALIC),
+        ));
+        return (vec![Line::from(spans)], 0);
+    }
+    // lines after the first are indented to start under the first one
+    let indent = " ".repeat(Line::from(spans.clone()).width());
+    let mut rows = vec![];
+    let mut max_code_scroll = 0;
+    // notices are written by the app, file names and the like are not markup
+    let raw = options.raw || msg.is_notice() || options.raw_users.contains(&msg.source);
+    let highlights = if msg.highlighted {
+        options.highlights
+    } else {
+        &[]
+    };
+    let blocks = if raw {
+        vec![Block::Text(&msg.content)]
+    } else {
+        blocks(&msg.content)
+    };
+    for block in &blocks {
+        match *block {
+            Block::Text(text) => rows.extend(
+                text.split('\n')
+                    .map(|text| compose_text(theme, text, highlights, options.search, !raw)),
+            ),
+            Block::Code { lang, code } => {
+                let width = options.width.saturating_sub(indent.len());
+                let (code_rows, max_scroll) = compose_code(theme, lang, code, width, code_scroll);
+                rows.extend(code_rows);
+                max_code_scroll = max_code_scroll.max(max_scroll);
+            }
+        }
+    }
+    let mut lines = vec![];
+    for (i, row) in rows.into_iter().enumerate() {
+        if i > 0 {
+            lines.push(Line::from(std::mem::replace(
+                &mut spans,
+                vec![Span::raw(indent.clone())],
+            )));
+        }
+        spans.extend(row);
+    }
+    // the status would not fit next to a code block as wide as the pane
+    if matches!(blocks.last(), Some(Block::Code { .. })) && (msg.edited || msg.status.is_some()) {
+        lines.push(Line::from(std::mem::replace(
+            &mut spans,
+            vec![Span::raw(indent.clone())],
+        )));
+    }
+    if msg.edited {
+        spans.push(Span::styled(" (edited)", Style::default().fg(theme.muted)));
+    }
+    match msg.status {
+        Some(DeliveryStatus::Pending(_)) => {
+            spans.push(Span::styled(" ○", Style::default().fg(theme.pending)))
+        }
+        Some(DeliveryStatus::Delivered) => {
+            spans.push(Span::styled(" ✓", Style::default().fg(theme.delivered)))
+        }
+        Some(DeliveryStatus::Failed) => spans.push(Span::styled(
+            " ✗ not delivered, press <r> to retry",
+            Style::default().fg(theme.failed),
+        )),
+        None => {}
+    }
+    lines.push(Line::from(spans));
+    (lines, max_code_scroll)
+}
+/// Rows of a bordered code block.
+/// The code does not wrap, it is clipped to the width and scrolled by the given columns,
+/// arrows on the borders showing the code out of sight.
+/// Returns the rows along with the maximum scroll.
+fn compose_code<'a>(
+    theme: &Theme,
+    lang: &str,
+    code: &str,
+    width: usize,
+    scroll: usize,
+) -> (Vec<Vec<Span<'a>>>, usize) {
+    let border = Style::default().fg(theme.muted);
+    // the code of the local user is not sanitized yet, it must be measured as shown
+    let code = highlight(&theme.code_theme, lang, &sanitize(code, true).text);
+    let line_width = |line: &[(Color, String)]| {
+        line.iter()
+            .map(|(_, text)| Line::from(text.as_str()).width())
+            .sum::<usize>()
+    };
+    let code_width = code.iter().map(|line| line_width(line)).max().unwrap_or(0);
+    // 2 columns of borders and 2 of padding
+    let inner = code_width.min(width.saturating_sub(4)).max(MIN_CODE_WIDTH);
+    let max_scroll = code_width.saturating_sub(inner);
+    let scroll = scroll.min(max_scroll);
+    let label = format!(" {} ", lang);
+    let top = if lang.is_empty() || label.chars().count() + 1 > inner + 2 {
+        vec![Span::styled(format!("╭{}╮", "─".repeat(inner + 2)), border)]
+    } else {
+        let rest = inner + 1 - label.chars().count();
+        vec![
+            Span::styled("╭─", border),
+            Span::styled(label, border.add_modifier(Modifier::BOLD)),
+            Span::styled(format!("{}╮", "─".repeat(rest)), border),
+        ]
+    };
+    let mut rows = vec![top];
+    for line in &code {
+        let left = if scroll > 0 { "‹ " } else { "│ " };
+        let right = if line_width(line) > scroll + inner {
+            " ›"
+        } else {
+            " │"
+        };
+        let mut row = vec![Span::styled(left, border)];
+        row.extend(clip_code(line, scroll, inner));
+        row.push(Span::styled(right, border));
+        rows.push(row);
+    }
+    rows.push(vec![Span::styled(
+        format!("╰{}╯", "─".repeat(inner + 2)),
+        border,
+    )]);
+    (rows, max_scroll)
+}
+/// The columns of a line of code from the start, padded to the width
+fn clip_code<'a>(line: &[(Color, String)], start: usize, width: usize) -> Vec<Span<'a>> {
+    let mut spans = vec![];
+    let mut column = 0;
+    let end = start + width;
+    for (color, text) in line {
+        let mut clipped = String::new();
+        for c in text.chars() {
+            let c_width = c.width().unwrap_or(0);
+            if column >= start && column + c_width <= end {
+                clipped.push(c);
+            } else if column < end && column + c_width > start {
+                // a wide character cut by an edge
+                clipped.push_str(&" ".repeat((column + c_width).min(end) - column.max(start)));
+            }
+            column += c_width;
+        }
+        if !clipped.is_empty() {
+            spans.push(Span::styled(clipped, Style::default().fg(*color)));
+        }
+    }
+    let shown = column.saturating_sub(start).min(width);
+    spans.push(Span::raw(" ".repeat(width - shown)));
+    spans
+}
+/// Spans of a line of text, formatted unless shown raw, the highlight terms standing out
+fn compose_text<'a>(
+    theme: &Theme,
+    line: &str,
+    highlights: &[String],
+    search: &[String],
+    markdown: bool,
+) -> Vec<Span<'a>> {
+    let highlight = Style::default()
+        .fg(theme.highlight_text)
+        .bg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = vec![];
+    let (line, base) = match quote(line).filter(|_| markdown) {
+        Some(quoted) => {
+            spans.push(Span::styled("▎ ", Style::default().fg(theme.muted)));
+            (quoted, Style::default().fg(theme.muted))
+        }
+        None => (line, Style::default()),
+    };
+    let pieces = if markdown {
+        inlines(line)
+    } else {
+        vec![Inline {
+            range: 0..line.len(),
+            format: Format::default(),
+        }]
+    };
+    let mut terms = find_terms(line, highlights);
+    terms.extend(find_substrings(line, search));
+    terms.sort_by_key(|term| term.start);
+    terms.dedup_by(|next, prev| next.start < prev.end);
+    for Inline { range, format } in pieces {
+        let style = format_style(theme, base, format);
+        let mut start = range.start;
+        for term in terms
+            .iter()
+            .filter(|term| term.start < range.end && term.end > range.start)
+        {
+            let (term_start, term_end) = (term.start.max(start), term.end.min(range.end));
+            if start < term_start {
+                spans.push(Span::styled(printable(&line[start..term_start]), style));
+            }
+            spans.push(Span::styled(
+                printable(&line[term_start..term_end]),
+                style.patch(highlight),
+            ));
+            start = term_end;
+        }
+        if start < range.end {
+            spans.push(Span::styled(printable(&line[start..range.end]), style));
+        }
+    }
+    spans
+}
+fn format_style(theme: &Theme, base: Style, format: Format) -> Style {
+    let mut style = base;
+    if format.bold {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    if format.italic {
+        style = style.add_modifier(Modifier::ITALIC);
+    }
+    if format.strike {
+        style = style.add_modifier(Modifier::CROSSED_OUT);
+    }
+    if format.link {
+        style = style.add_modifier(Modifier::UNDERLINED);
+    }
+    if format.code {
+        style = style.fg(theme.accent);
+    }
+    style
+}
+/// Control characters would reach the terminal as they are, escape sequences included
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_control() {
+                char::REPLACEMENT_CHARACTER
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+/// Vertical scroll of the message pane.
+/// The pane follows the newest messages unless scrolled back, and always keeps the focused lines in view.
+/// Returns the scroll along with the maximum scroll back.
+pub fn pane_scroll(
+    lines: &[Line],
+    width: u16,
+    height: u16,
+    scroll_back: usize,
+    focus: Option<Range<usize>>,
+) -> (u16, usize) {
+    let width = width.max(1) as usize;
+    // rows each line takes once wrapped, word wrapping may take a bit more
+    let rows = lines
+        .iter()
+        .map(|line| line.width().div_ceil(width).max(1))
+        .collect::<Vec<_>>();
+    let total: usize = rows.iter().sum();
+    let height = height as usize;
+    let max_scroll_back = total.saturating_sub(height);
+    let mut top = max_scroll_back - scroll_back.min(max_scroll_back);
+    if let Some(focus) = focus {
+        let focus_top: usize = rows[..focus.start].iter().sum();
+        let focus_bottom: usize = rows[..focus.end].iter().sum();
+        if focus_top < top {
+            top = focus_top;
+        } else if focus_bottom > top + height {
+            top = focus_bottom.saturating_sub(height);
+        }
+    }
+    (top as u16, max_scroll_back)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(row: &[Span]) -> String {
+        row.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn measures_code_as_shown() {
+        let theme = Theme::default();
+        let (rows, max_scroll) = compose_code(&theme, "", "\tx\r\n\u{1b}[2Jy", 40, 0);
+        let rows = rows.iter().map(|row| text(row)).collect::<Vec<_>>();
+        assert_eq!(max_scroll, 0);
+        assert_eq!(rows[1], "│     x    │");
+        assert_eq!(rows[2], "│ ␛[2Jy    │");
+        assert!(rows
+            .iter()
+            .all(|row| Line::from(row.as_str()).width() == 12));
+    }
+
+    #[test]
+    fn clips_code_to_the_width() {
+        let theme = Theme::default();
+        let (rows, max_scroll) = compose_code(&theme, "", "a\t\u{7}bcdefgh", 12, 3);
+        assert_eq!(max_scroll, 5);
+        assert_eq!(text(&rows[1]), "‹   ␇bcdef ›");
+    }
+}
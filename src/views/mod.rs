@@ -1 +1,5 @@
+pub mod colors;
+pub mod highlight;
+pub mod messages;
 pub mod renderer;
+pub mod theme;
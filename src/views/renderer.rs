@@ -1,174 +1,665 @@
-use crate::models::{message::Message, modes::InputMode, session::Session};
-use crossterm::event::{poll, read, Event, Event::Key, KeyCode};
+use super::{
+    messages::{compose_msgs, pane_scroll, ComposeOptions},
+    theme::Theme,
+};
+use crate::models::{
+    commands::COMMANDS,
+    completion::Completion,
+    config::{DesktopNotification, NotificationsConfig},
+    connection::ConnectionState,
+    emoji::QUICK_REACTIONS,
+    endpoints::Endpoints,
+    keymap::{Action, KeyContext, Keymap},
+    modes::InputMode,
+    session::Session,
+    transfer::{self, TransferState},
+    user::User,
+};
+use chrono::{Local, TimeZone};
+use crossterm::event::{poll, read, Event, Event::Key, KeyCode, KeyModifiers};
 use ratatui::{
     backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame, Terminal,
 };
-use std::{io, time::Duration};
-use tui_input::{backend::crossterm::EventHandler, Input};
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
 
-const COLOR_CLU: Color = Color::Rgb(235, 124, 57);
-pub const COLOR_TRON: Color = Color::LightBlue;
-const BORDER_TYPE: BorderType = BorderType::Rounded;
-const BORDERS_DIR: Borders = Borders::ALL;
 const MSG_REFRESH_RATE_MS: u64 = 100;
+/// lines scrolled by PageUp/PageDown in the message pane
+const SCROLL_STEP: usize = 10;
+/// lines of text the input box grows to before scrolling
+const MAX_INPUT_LINES: usize = 8;
 
 pub async fn start_renderer<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut Session,
 ) -> io::Result<()> {
+    // kept across iterations so a key read while a message arrives is not lost
+    let mut listen_for_keys: Option<JoinHandle<Option<Event>>> = None;
     loop {
+        app.tick();
+        for notification in app.take_notifications() {
+            notify(&notification, &app.config.notifications)?;
+        }
         terminal.draw(|frame| update_ui(frame, app))?;
-        let listen_for_keys = tokio::spawn(async {
-            if poll(Duration::from_millis(MSG_REFRESH_RATE_MS)).unwrap() {
-                if let Key(key) = read().unwrap() {
-                    Some(key)
+        let keys = listen_for_keys.get_or_insert_with(|| {
+            // polling blocks, keep it off the async workers so the server is not starved
+            tokio::task::spawn_blocking(|| {
+                if poll(Duration::from_millis(MSG_REFRESH_RATE_MS)).unwrap() {
+                    Some(read().unwrap())
                 } else {
                     None
                 }
-            } else {
-                None
-            }
+            })
         });
         tokio::select! {
             _ = app.listen_for_msgs() => {},
-            k = listen_for_keys => {
-                if let Some(key) = k.unwrap() {
-                    match app.input_mode {
-                        InputMode::Help | InputMode::Info(_) => match key.code {
-                            KeyCode::Char(':') => app.switch_mode(InputMode::Command),
-                            KeyCode::Char('t') => app.switch_mode(InputMode::Typing),
-                            KeyCode::Char('Q') => return Ok(()),
-                            _ => app.switch_mode(InputMode::Normal),
-                        },
-                        InputMode::Normal => match key.code {
-                            KeyCode::Char(':') => app.switch_mode(InputMode::Command),
-                            KeyCode::Char('t') => app.switch_mode(InputMode::Typing),
-                            KeyCode::Char('h') => app.switch_mode(InputMode::Help),
-                            KeyCode::Char('Q') => return Ok(()),
-                            // TODO: other functionalities (scroll)
-                            _ => (),
-                        },
-                        InputMode::Command => match key.code {
-                            KeyCode::Esc => app.switch_mode(InputMode::Normal),
-                            KeyCode::Enter => match app.execute_cmd() {
-                                Ok(mode) => app.switch_mode(mode),
-                                Err(()) => return Ok(()), // gracefully shutdown
-                            },
-                            _ => { app.text_buffer.handle_event(&Event::Key(key)); }
-                        },
-                        InputMode::Typing => match key.code {
-                            KeyCode::Esc => app.switch_mode(InputMode::Normal),
-                            KeyCode::Enter => app.send_user_msg().await,
-                            _ => { app.text_buffer.handle_event(&Event::Key(key)); }
-                        },
+            k = keys => {
+                listen_for_keys = None;
+                let key = match k.unwrap() {
+                    Some(Key(key)) => Some(key),
+                    Some(Event::Paste(text)) => {
+                        app.paste(&text);
+                        None
+                    }
+                    Some(Event::FocusGained) => {
+                        app.focused = true;
+                        None
+                    }
+                    Some(Event::FocusLost) => {
+                        app.focused = false;
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some(key) = key {
+                    let action = app.keymap.action(&app.input_mode, key);
+                    // any other key settles on the current suggestion
+                    if !matches!(action, Some(Action::Complete | Action::CompletePrev)) {
+                        app.completion = None;
+                    }
+                    if app.history_search.is_some() {
+                        match key.code {
+                            _ if action == Some(Action::SearchHistory) => app.search_history(),
+                            KeyCode::Char('g') if key.modifiers == KeyModifiers::CONTROL => app.end_history_search(false),
+                            KeyCode::Esc => app.end_history_search(false),
+                            KeyCode::Enter => app.end_history_search(true),
+                            KeyCode::Backspace => app.edit_history_query(|query| {
+                                query.pop();
+                            }),
+                            KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                                app.edit_history_query(|query| query.push(c))
+                            }
+                            // any other key accepts the match and edits it
+                            _ => {
+                                app.end_history_search(true);
+                                app.text_buffer.handle_key(key);
+                            }
+                        }
+                    } else if let Some(action) = action {
+                        if perform(app, action).await.is_err() {
+                            return Ok(()); // gracefully shutdown
+                        }
+                    } else {
+                        match app.input_mode {
+                            // any unbound key dismisses the popups
+                            InputMode::Help | InputMode::Info(_) => app.switch_mode(InputMode::Normal),
+                            InputMode::Typing => {
+                                if app.text_buffer.handle_key(key) {
+                                    app.on_text_edited();
+                                }
+                            }
+                            InputMode::Command => {
+                                app.text_buffer.handle_key(key);
+                            }
+                            InputMode::Search => app.edit_search(key),
+                            InputMode::Normal
+                            | InputMode::Connections
+                            | InputMode::Discovery
+                            | InputMode::Links => {}
+                        }
                     }
                 }
             }
         }
     }
 }
+/// Carries out a bound action, Err(()) quits the app
+async fn perform(app: &mut Session, action: Action) -> Result<(), ()> {
+    match action {
+        Action::CommandMode => app.switch_mode(InputMode::Command),
+        Action::TypingMode => app.switch_mode(InputMode::Typing),
+        Action::NormalMode => app.switch_mode(InputMode::Normal),
+        Action::Help => app.switch_mode(InputMode::Help),
+        Action::Quit => return Err(()),
+        Action::RetryFailed => app.retry_failed_msgs(),
+        Action::Connections => app.switch_mode(InputMode::Connections),
+        Action::SelectPrev => match app.input_mode {
+            InputMode::Connections => app.endpoints.select_prev(),
+            InputMode::Discovery => app.select_prev_discovered(),
+            InputMode::Links => app.select_prev_link(),
+            InputMode::Search => app.select_prev_result(),
+            _ => app.select_prev_msg(),
+        },
+        Action::SelectNext => match app.input_mode {
+            InputMode::Connections => app.endpoints.select_next(),
+            InputMode::Discovery => app.select_next_discovered(),
+            InputMode::Links => app.select_next_link(),
+            InputMode::Search => app.select_next_result(),
+            _ => app.select_next_msg(),
+        },
+        Action::Connect => match app.input_mode {
+            InputMode::Discovery => app.connect_discovered_room(),
+            _ => app.connect_selected_endpoint(),
+        },
+        Action::AddEndpoint => match app.input_mode {
+            InputMode::Discovery => app.save_discovered_room(),
+            _ => app.edit_endpoint(false),
+        },
+        Action::Unselect => app.unselect(),
+        Action::Reply => app.reply_to_selected_msg(),
+        Action::ToggleThread => app.toggle_thread(),
+        Action::QuickReaction(i) => app.react_to_selected_msg(QUICK_REACTIONS[i]),
+        Action::Edit => match app.input_mode {
+            InputMode::Connections => app.edit_endpoint(true),
+            InputMode::Discovery => {}
+            _ => app.edit_selected_msg(),
+        },
+        Action::Delete => match app.input_mode {
+            InputMode::Connections => app.delete_selected_endpoint(),
+            InputMode::Discovery => {}
+            _ => app.delete_selected_msg(),
+        },
+        Action::ScrollUp => app.scroll_back += SCROLL_STEP,
+        Action::ScrollDown => app.scroll_back = app.scroll_back.saturating_sub(SCROLL_STEP),
+        Action::ScrollToBottom => app.scroll_back = 0,
+        Action::ScrollCodeLeft => app.scroll_code(false),
+        Action::ScrollCodeRight => app.scroll_code(true),
+        Action::CopyCode => app.copy_code(),
+        Action::Links => app.pick_link(),
+        Action::OpenLink => app.open_selected_link(),
+        Action::CopyLink => app.copy_selected_link(),
+        Action::Search => {
+            let mode = app.open_search(None);
+            app.switch_mode(mode);
+        }
+        Action::JumpToResult => app.jump_to_result(),
+        Action::Send => app.send_user_msg().await,
+        Action::Execute => {
+            let mode = app.execute_cmd()?;
+            app.switch_mode(mode);
+        }
+        Action::NewLine => {
+            app.text_buffer.insert_newline();
+            app.on_text_edited();
+        }
+        // the history is browsed from the first and last lines
+        Action::HistoryPrev => {
+            if !app.text_buffer.move_up() {
+                app.history_prev();
+            }
+        }
+        Action::HistoryNext => {
+            if !app.text_buffer.move_down() {
+                app.history_next();
+            }
+        }
+        Action::SearchHistory => app.search_history(),
+        Action::Complete => app.complete(true),
+        Action::CompletePrev => app.complete(false),
+    }
+    Ok(())
+}
 fn update_ui<B: Backend>(frame: &mut Frame<B>, app: &mut Session) {
+    // the input box grows with its content, plus 2 for borders
+    let input_rows = app.input().lines().len().clamp(1, MAX_INPUT_LINES);
     let parent = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(85), Constraint::Percentage(15)])
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(input_rows as u16 + 2),
+            Constraint::Length(1),
+        ])
         .split(frame.size());
 
+    let grid = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(80), Constraint::Percentage(20)])
+        .split(parent[0]);
+    let pane = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(grid[0]);
+
+    let theme = &app.theme;
     // TODO: cache previous messages to avoid re-iterating and recreating the vector each time
-    let messages = app.messages.iter().map(compose_msg).collect::<Vec<_>>();
-    let messages = Paragraph::new(messages).wrap(Wrap { trim: false }).block(
-        Block::default()
-            .title(Line::from(" The Grid "))
-            .title_alignment(Alignment::Center)
-            .borders(BORDERS_DIR)
-            .border_type(BORDER_TYPE)
-            .style(Style::default().fg(COLOR_TRON)),
+    let thread = app.shown_ids();
+    let selected = app.focused_msg();
+    let (messages, ranges, max_code_scroll) = compose_msgs(
+        &app.messages,
+        &ComposeOptions {
+            theme,
+            me: &app.root_user().name,
+            highlights: &app.highlight_terms(),
+            search: app
+                .search
+                .as_ref()
+                .map_or(&[], |search| &search.query.terms),
+            timestamps: app.config.display.timestamps,
+            selected,
+            thread: thread.as_ref(),
+            width: pane[0].width.saturating_sub(2) as usize,
+            code_scroll: app.code_scroll,
+            raw: app.raw,
+            raw_users: &app.raw_users,
+        },
+    );
+    let title = match (
+        &app.search,
+        app.thread
+            .as_ref()
+            .and_then(|root| app.messages.iter().find(|msg| msg.id == *root)),
+    ) {
+        (Some(search), _) if !search.query.is_empty() => match search.hits.len() {
+            1 => String::from(" Search · 1 message "),
+            found => format!(" Search · {} messages ", found),
+        },
+        (_, Some(root)) => format!(" Thread · {}: {} ", root.source, root.snippet(30)),
+        _ => String::from(" The Grid "),
+    };
+    // keep 2 for borders
+    let (scroll, max_scroll_back) = pane_scroll(
+        &messages,
+        pane[0].width.saturating_sub(2),
+        pane[0].height.saturating_sub(2),
+        app.scroll_back,
+        selected.and_then(|i| ranges.get(i).cloned()),
     );
-    frame.render_widget(messages, parent[0]);
+    // the pane stays where the selected message is, once it is unselected too
+    if std::mem::take(&mut app.scroll_to_selected) {
+        app.scroll_back = max_scroll_back - scroll as usize;
+    }
+    app.scroll_back = app.scroll_back.min(max_scroll_back);
+    app.code_scroll = app.code_scroll.min(max_code_scroll);
+    let messages = Paragraph::new(messages)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0))
+        .block(
+            Block::default()
+                .title(Line::from(title))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_type(theme.border.into())
+                .style(Style::default().fg(theme.frame)),
+        );
+    frame.render_widget(messages, pane[0]);
+    frame.render_widget(typing_line(theme, app.typers().collect()), pane[1]);
+    frame.render_widget(users_list(theme, app.users()), grid[1]);
+    frame.render_widget(status_bar(theme, app), parent[2]);
 
-    let width = parent[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
-    let scroll = app.text_buffer.visual_scroll(width as usize);
-    let text_box = textbox(&app.input_mode, &app.text_buffer, scroll).wrap(Wrap { trim: false });
-    frame.render_widget(text_box, parent[1]);
+    // keep the cursor in view, 2 columns are taken by borders and 1 by the cursor
+    let (row, before_cursor) = app.input().cursor();
+    let cursor_x = Line::from(before_cursor).width();
+    let width = parent[1].width.saturating_sub(3) as usize;
+    let scroll = (
+        row.saturating_sub(input_rows - 1),
+        cursor_x.saturating_sub(width),
+    );
+    let title = if let Some(search) = &app.history_search {
+        let failing = if search.failing { "failing " } else { "" };
+        format!(" {}reverse-i-search `{}` ", failing, search.query)
+    } else if let Some(error) = app.search.as_ref().and_then(|search| search.error.as_ref()) {
+        format!(" Search · {} ", error)
+    } else if app.is_editing() {
+        String::from(" Editing message ")
+    } else if let Some(parent) = app.replying_to() {
+        format!(" Replying to {} ", parent.source)
+    } else {
+        app.input_mode.to_string()
+    };
+    frame.render_widget(
+        textbox(theme, &app.input_mode, title, app.input().lines(), scroll),
+        parent[1],
+    );
 
     match &app.input_mode {
-        InputMode::Typing | InputMode::Command => {
+        InputMode::Typing | InputMode::Command | InputMode::Search => {
             // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
             frame.set_cursor(
-                // Put cursor past the end of the input text
-                parent[1].x + ((app.text_buffer.visual_cursor()).max(scroll) - scroll) as u16 + 1,
-                // Move one line down, from the border to the input line
-                parent[1].y + 1,
-            )
+                // Put cursor past the border, where it stands in the text
+                parent[1].x + (cursor_x - scroll.1) as u16 + 1,
+                parent[1].y + (row - scroll.0) as u16 + 1,
+            );
+            if let Some(completion) = &app.completion {
+                let word = Line::from(app.text_buffer.word_before_cursor()).width();
+                let x = parent[1].x + (cursor_x - scroll.1).saturating_sub(word) as u16 + 1;
+                display_suggestions(frame, theme, completion, x, parent[1].y);
+            }
         }
-        InputMode::Info(msg) => display_popup(frame, "INFO", construct_paragraph(msg)),
-        InputMode::Help => display_help_popup(frame),
+        InputMode::Info(msg) => display_popup(frame, theme, "INFO", construct_paragraph(msg)),
+        InputMode::Help => display_help_popup(frame, theme, &app.keymap),
+        InputMode::Connections => display_connections(frame, theme, &app.endpoints, &app.keymap),
+        InputMode::Discovery => display_discovery(frame, theme, app),
+        InputMode::Links => display_links(frame, theme, app),
         _ => {}
     }
 }
-/// Composes a user message to be rendered
-fn compose_msg<'a>(msg: &Message) -> Line<'a> {
-    Line::from(vec![
+/// Rings the bell and/or asks the terminal for a desktop notification
+fn notify(body: &str, config: &NotificationsConfig) -> io::Result<()> {
+    // the body ends up inside an escape sequence, it must not be able to terminate it
+    let body = body.chars().filter(|c| !c.is_control()).collect::<String>();
+    let mut out = io::stdout();
+    if config.bell {
+        out.write_all(b"\x07")?;
+    }
+    match config.desktop {
+        DesktopNotification::Osc9 => write!(out, "\x1b]9;{}\x07", body)?,
+        DesktopNotification::Osc777 => write!(out, "\x1b]777;notify;endl-rc;{}\x07", body)?,
+        DesktopNotification::Off => {}
+    }
+    out.flush()
+}
+/// Tells who is typing, e.g. "alice is typing…"
+fn typing_line<'a>(theme: &Theme, typers: Vec<&str>) -> Paragraph<'a> {
+    let text = match typers.as_slice() {
+        [] => String::new(),
+        [name] => format!(" {} is typing…", name),
+        [first, second] => format!(" {} and {} are typing…", first, second),
+        _ => String::from(" several people are typing…"),
+    };
+    Paragraph::new(text).style(
+        Style::default()
+            .fg(theme.muted)
+            .add_modifier(Modifier::ITALIC),
+    )
+}
+/// e.g. "flynn · localhost:8080 (host) · ● connected · 2 peers · 12ms · unencrypted"
+fn status_bar<'a>(theme: &Theme, app: &Session) -> Paragraph<'a> {
+    let separator = || Span::styled(" · ", Style::default().fg(theme.muted));
+    let me = app.root_user();
+    let mut spans = vec![
         Span::styled(
-            format!(" <{}>  ", msg.source),
-            Style::default().add_modifier(Modifier::BOLD).fg(msg.color),
+            format!(" {}", me.name),
+            Style::default()
+                .fg(theme.user(me.color))
+                .add_modifier(Modifier::BOLD),
         ),
-        Span::raw(msg.content.to_string()),
-    ])
+        separator(),
+    ];
+    let role = if app.is_hosting() { "host" } else { "joined" };
+    spans.push(match &app.room {
+        Some(room) => Span::raw(format!("{} ({})", room, role)),
+        None => Span::styled("no room", Style::default().fg(theme.muted)),
+    });
+    spans.push(separator());
+    let color = match app.connection {
+        ConnectionState::Connected => theme.connected,
+        ConnectionState::Connecting | ConnectionState::Reconnecting(_) => theme.connecting,
+        ConnectionState::Disconnected => theme.failed,
+        ConnectionState::Offline => theme.muted,
+    };
+    spans.push(Span::styled(
+        format!("● {}", app.connection),
+        Style::default().fg(color),
+    ));
+    if app.connection == ConnectionState::Connected {
+        let peers = app.users().len() - 1;
+        spans.push(separator());
+        spans.push(Span::raw(match peers {
+            1 => String::from("1 peer"),
+            peers => format!("{} peers", peers),
+        }));
+        // clients only measure the link to the host, the host the links to every client
+        let latencies = app
+            .users()
+            .iter()
+            .filter_map(|user| user.latency)
+            .collect::<Vec<_>>();
+        if !latencies.is_empty() {
+            let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+            spans.push(separator());
+            spans.push(Span::raw(format!("{}ms", average.as_millis())));
+        }
+        // TODO: show the cipher once links are encrypted
+        spans.push(separator());
+        spans.push(Span::styled(
+            "unencrypted",
+            Style::default().fg(theme.muted),
+        ));
+    }
+    for transfer in app
+        .transfers
+        .iter()
+        .filter(|transfer| matches!(transfer.state, TransferState::Active(_)))
+    {
+        spans.push(separator());
+        let peer = transfer.peer.as_deref().unwrap_or_default();
+        spans.push(Span::styled(
+            match transfer.direction {
+                transfer::Direction::Incoming => {
+                    format!("↓ {} {}%", transfer.file_name, transfer.progress())
+                }
+                transfer::Direction::Outgoing => format!(
+                    "↑ {} → {} {}%",
+                    transfer.file_name,
+                    peer,
+                    transfer.progress()
+                ),
+            },
+            Style::default().fg(theme.pending),
+        ));
+    }
+    if app.unread > 0 {
+        spans.push(separator());
+        let mentions = match app.unread_mentions {
+            0 => String::new(),
+            1 => String::from(", 1 mention"),
+            mentions => format!(", {} mentions", mentions),
+        };
+        spans.push(Span::styled(
+            format!("{} unread{}", app.unread, mentions),
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    Paragraph::new(Line::from(spans))
+}
+/// Lists the users in the room along with their latency
+fn users_list<'a>(theme: &Theme, users: &[User]) -> Paragraph<'a> {
+    let users = users
+        .iter()
+        .map(|user| {
+            let latency = user
+                .latency
+                .map(|rtt| format!(" {}ms", rtt.as_millis()))
+                .unwrap_or_default();
+            Line::from(vec![
+                Span::styled(
+                    format!(" {}", user.name),
+                    Style::default().fg(theme.user(user.color)),
+                ),
+                Span::styled(latency, Style::default().fg(theme.muted)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    Paragraph::new(users).block(
+        Block::default()
+            .title(Line::from(" Users "))
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_type(theme.border.into())
+            .style(Style::default().fg(theme.frame)),
+    )
 }
 
-fn textbox<'a>(state: &InputMode, input: &'a Input, scroll: usize) -> Paragraph<'a> {
+fn textbox<'a>(
+    theme: &Theme,
+    state: &InputMode,
+    title: String,
+    lines: &'a [String],
+    (rows, columns): (usize, usize),
+) -> Paragraph<'a> {
     let style = match state {
-        InputMode::Typing | InputMode::Command => Style::default().fg(COLOR_CLU),
-        _ => Style::default().fg(COLOR_TRON),
+        InputMode::Typing | InputMode::Command | InputMode::Search => {
+            Style::default().fg(theme.accent)
+        }
+        _ => Style::default().fg(theme.frame),
     };
-    Paragraph::new(input.value())
-        .style(style)
-        .scroll((0, scroll as u16))
-        .block(
-            Block::default()
-                .borders(BORDERS_DIR)
-                .border_type(BORDER_TYPE)
-                .title(state.to_string()),
-        )
+    Paragraph::new(
+        lines
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect::<Vec<_>>(),
+    )
+    .style(style)
+    .scroll((rows as u16, columns as u16))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(theme.border.into())
+            .title(title),
+    )
 }
-fn construct_paragraph(message: &str) -> Paragraph {
+fn construct_paragraph(message: &str) -> Paragraph<'_> {
     Paragraph::new(message).alignment(Alignment::Center)
 }
-fn display_help_popup<B: Backend>(frame: &mut Frame<B>) {
-    const COMMANDS: &str = r#"
-Normal Mode
-Press <*> to ****
-Press <Q> to Quit
-Press <:> to enter Command mode
-Press <t> to enter Typing mode
-Press <h> to show this help message
-
-Command Mode
-Enter "join <link>" to join a room
-Enter "run" to start hosting a room
-Enter "inv" to copy session link to clipboard
-Press <Esc> to Switch back to Normal mode"#;
+/// Lists the effective key bindings of each mode along with the commands
+fn display_help_popup<B: Backend>(frame: &mut Frame<B>, theme: &Theme, keymap: &Keymap) {
+    let normal = format!(
+        "\n{}",
+        describe_bindings("Normal Mode", keymap, KeyContext::Normal)
+    );
+    let mut modes = format!(
+        "\n{}",
+        describe_bindings("Typing Mode", keymap, KeyContext::Typing)
+    );
+    // editing keys are not configurable
+    modes.push_str("Press <Ctrl-K>/<Ctrl-U>/<Ctrl-W> to kill, <Ctrl-Y>/<Alt-Y> to yank\n\n");
+    modes.push_str(&describe_bindings(
+        "Command Mode",
+        keymap,
+        KeyContext::Command,
+    ));
+    for command in COMMANDS {
+        let aliases = match command.aliases {
+            [] => String::new(),
+            aliases => format!(" ({})", aliases.join(", ")),
+        };
+        modes.push_str(&format!(
+            "Enter \"{}\"{} to {}\n",
+            command.usage(),
+            aliases,
+            command.description
+        ));
+    }
 
-    display_popup(
-        frame,
-        " Welcome to the End of Line Club ",
-        construct_paragraph(COMMANDS),
+    let area = centered_rect(frame.size(), 90);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Block::default()
+            .title(" Welcome to the End of Line Club ")
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_type(theme.border.into())
+            .style(popup_style(theme)),
+        area,
     );
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    frame.render_widget(construct_paragraph(&normal), columns[0]);
+    frame.render_widget(construct_paragraph(&modes), columns[1]);
 }
-fn display_popup<B: Backend>(frame: &mut Frame<B>, title: &str, message: Paragraph) {
+/// e.g. "Press <j>/<Down> to select the next message", one line per bound action
+fn describe_bindings(title: &str, keymap: &Keymap, context: KeyContext) -> String {
+    let mut help = format!("{}\n", title);
+    let mut reactions = vec![];
+    for (action, chords) in keymap.describe(context) {
+        let chords = chords
+            .iter()
+            .map(|chord| format!("<{}>", chord))
+            .collect::<Vec<_>>()
+            .join("/");
+        match action {
+            // one line for all of them
+            Action::QuickReaction(i) => reactions.push((chords, QUICK_REACTIONS[i])),
+            action => help.push_str(&format!("Press {} to {}\n", chords, action.description())),
+        }
+    }
+    if !reactions.is_empty() {
+        let (chords, emojis): (Vec<_>, Vec<_>) = reactions.into_iter().unzip();
+        help.push_str(&format!(
+            "Press {} to react with {}\n",
+            chords.join(" "),
+            emojis.join(" ")
+        ));
+    }
+    help.push('\n');
+    help
+}
+/// Lists the completion candidates in a box standing above the input box, from column x
+fn display_suggestions<B: Backend>(
+    frame: &mut Frame<B>,
+    theme: &Theme,
+    completion: &Completion,
+    x: u16,
+    bottom: u16,
+) {
+    const MAX_SUGGESTIONS: usize = 6;
+    let shown = completion.candidates.len().min(MAX_SUGGESTIONS);
+    // scroll the list so the selected candidate is shown
+    let first = (completion.selected + 1).saturating_sub(shown);
+    let lines = completion.candidates[first..first + shown]
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let style = if first + i == completion.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::styled(format!(" {} ", candidate), style)
+        })
+        .collect::<Vec<_>>();
+    let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16 + 2;
+    let height = shown as u16 + 2;
+    let screen = frame.size();
+    let area = Rect::new(
+        x.min(screen.width.saturating_sub(width)),
+        bottom.saturating_sub(height),
+        width.min(screen.width),
+        height.min(bottom),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(theme.border.into())
+                .style(popup_style(theme)),
+        ),
+        area,
+    );
+}
+fn display_popup<B: Backend>(frame: &mut Frame<B>, theme: &Theme, title: &str, message: Paragraph) {
     let help_block = Block::default()
         .title(title)
         .title_alignment(Alignment::Center)
-        .borders(BORDERS_DIR)
-        .border_type(BORDER_TYPE)
-        .style(Style::default().fg(COLOR_CLU));
+        .borders(Borders::ALL)
+        .border_type(theme.border.into())
+        .style(popup_style(theme));
 
-    let area = centered_rect(frame.size());
+    let area = centered_rect(frame.size(), 50);
     frame.render_widget(Clear, area);
     frame.render_widget(help_block, area);
 
@@ -179,22 +670,226 @@ fn display_popup<B: Backend>(frame: &mut Frame<B>, title: &str, message: Paragra
 
     frame.render_widget(message, chunk[0]);
 }
-fn centered_rect(area: Rect) -> Rect {
+/// Lists the saved rooms then the recent ones, with the keys to manage them
+fn display_connections<B: Backend>(
+    frame: &mut Frame<B>,
+    theme: &Theme,
+    endpoints: &Endpoints,
+    keymap: &Keymap,
+) {
+    let muted = Style::default().fg(theme.muted);
+    let mut lines = vec![];
+    for (i, endpoint) in endpoints.endpoints().iter().enumerate() {
+        let mut spans = vec![
+            Span::raw(if endpoint.saved { " ★ " } else { "   " }),
+            Span::styled(
+                endpoint.name().to_owned(),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+        ];
+        if endpoint.nickname.is_some() {
+            spans.push(Span::styled(format!("  {}", endpoint.link), muted));
+        }
+        let last_seen = endpoint
+            .last_seen
+            .and_then(|millis| Local.timestamp_millis_opt(millis).single())
+            .map(|time| format!("  seen {}", time.format("%d %b %H:%M")))
+            .unwrap_or_else(|| String::from("  never joined"));
+        spans.push(Span::styled(last_seen, muted));
+        if let Some(fingerprint) = &endpoint.fingerprint {
+            spans.push(Span::styled(format!("  pinned {}", fingerprint), muted));
+        }
+        let mut line = Line::from(spans);
+        if i == endpoints.selected {
+            line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(Line::styled(
+            " No rooms yet, the ones you join are listed here",
+            muted,
+        ));
+    }
+    display_room_list(
+        frame,
+        theme,
+        " Connections ",
+        lines,
+        endpoints.selected,
+        room_list_keys(keymap, true),
+    );
+}
+/// Lists the rooms announced on the local network
+fn display_discovery<B: Backend>(frame: &mut Frame<B>, theme: &Theme, app: &Session) {
+    let muted = Style::default().fg(theme.muted);
+    let mut lines = app
+        .discovered
+        .iter()
+        .enumerate()
+        .map(|(i, room)| {
+            let announcement = &room.announcement;
+            let users = match announcement.users {
+                1 => String::from("1 user"),
+                users => format!("{} users", users),
+            };
+            let mut line = Line::from(vec![
+                Span::raw(if announcement.password {
+                    " 🔒 "
+                } else {
+                    "    "
+                }),
+                Span::styled(
+                    announcement.room.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    format!(
+                        "  hosted by {} on {}  {}",
+                        announcement.host, room.link, users
+                    ),
+                    muted,
+                ),
+            ]);
+            if i == app.discovered_selected {
+                line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            line
+        })
+        .collect::<Vec<_>>();
+    if app.is_discovering() {
+        lines.push(Line::styled(" Looking for rooms…", muted));
+    } else if lines.is_empty() {
+        lines.push(Line::styled(
+            " No room found, hosts announce their rooms with run --announce",
+            muted,
+        ));
+    }
+    display_room_list(
+        frame,
+        theme,
+        " Local Network ",
+        lines,
+        app.discovered_selected,
+        room_list_keys(&app.keymap, false),
+    );
+}
+/// e.g. "<Enter> join · <a> add", the editing keys only apply to the saved rooms
+fn room_list_keys(keymap: &Keymap, editable: bool) -> String {
+    keymap
+        .describe(KeyContext::Connections)
+        .into_iter()
+        .filter_map(|(action, chords)| {
+            let label = match action {
+                Action::Connect => "join",
+                Action::AddEndpoint if editable => "add",
+                Action::AddEndpoint => "save",
+                Action::Edit if editable => "edit",
+                Action::Delete if editable => "delete",
+                Action::NormalMode => "close",
+                _ => return None,
+            };
+            Some(format!("<{}> {}", chords[0], label))
+        })
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+/// Lists the links of the messages shown, the newest first
+fn display_links<B: Backend>(frame: &mut Frame<B>, theme: &Theme, app: &Session) {
+    let lines = app
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, (url, sender))| {
+            let mut line = Line::from(vec![
+                Span::raw(" "),
+                Span::styled(
+                    url.clone(),
+                    Style::default().add_modifier(Modifier::UNDERLINED),
+                ),
+                Span::styled(
+                    format!("  from {}", sender),
+                    Style::default().fg(theme.muted),
+                ),
+            ]);
+            if i == app.links_selected {
+                line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            line
+        })
+        .collect();
+    let keys = app
+        .keymap
+        .describe(KeyContext::Links)
+        .into_iter()
+        .filter_map(|(action, chords)| {
+            let label = match action {
+                Action::OpenLink => "open",
+                Action::CopyLink => "copy",
+                Action::NormalMode => "close",
+                _ => return None,
+            };
+            Some(format!("<{}> {}", chords[0], label))
+        })
+        .collect::<Vec<_>>()
+        .join(" · ");
+    display_room_list(frame, theme, " Links ", lines, app.links_selected, keys);
+}
+/// A popup listing rooms or links, the keys to use are shown at the bottom
+fn display_room_list<B: Backend>(
+    frame: &mut Frame<B>,
+    theme: &Theme,
+    title: &str,
+    lines: Vec<Line>,
+    selected: usize,
+    keys: String,
+) {
+    let area = centered_rect(frame.size(), 70);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Block::default()
+            .title(title)
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_type(theme.border.into())
+            .style(popup_style(theme)),
+        area,
+    );
+    let chunks = Layout::default()
+        .margin(1)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(area);
+    // keep the selected room in view
+    let scroll = (selected + 1).saturating_sub(chunks[0].height as usize);
+    frame.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)), chunks[0]);
+    frame.render_widget(
+        Paragraph::new(keys)
+            .style(Style::default().fg(theme.muted))
+            .alignment(Alignment::Center),
+        chunks[1],
+    );
+}
+fn popup_style(theme: &Theme) -> Style {
+    Style::default().fg(theme.accent).bg(theme.popup_bg)
+}
+/// A rect centered in the area, taking the given percentage of its width and height
+fn centered_rect(area: Rect, percent: u16) -> Rect {
+    let margin = (100 - percent) / 2;
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Percentage(25),
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
+            Constraint::Percentage(margin),
+            Constraint::Percentage(percent),
+            Constraint::Percentage(margin),
         ])
         .split(area);
 
     Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(25),
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
+            Constraint::Percentage(margin),
+            Constraint::Percentage(percent),
+            Constraint::Percentage(margin),
         ])
         .split(popup_layout[1])[1]
 }
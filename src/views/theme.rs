@@ -0,0 +1,215 @@
+use ratatui::{style::Color, widgets::BorderType};
+use serde::{Deserialize, Deserializer};
+use std::{fs, str::FromStr};
+
+use super::colors::{nearest_ansi, supports_truecolor};
+use super::highlight::{code_themes, NO_HIGHLIGHTING};
+use crate::models::config::config_dir;
+
+/// directory of the theme files, within the config directory
+const THEMES_DIR: &str = "themes";
+pub const BUILTIN_THEMES: [&str; 3] = ["tron", "high-contrast", "monochrome"];
+
+/// Colors and borders of the UI.
+/// Theme files hold any of the fields, missing ones are taken from the Tron theme.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    #[serde(skip)]
+    pub name: String,
+    pub border: ThemeBorder,
+    /// borders of the input box while typing and of the popups
+    #[serde(deserialize_with = "color")]
+    pub accent: Color,
+    /// borders of the panes
+    #[serde(deserialize_with = "color")]
+    pub frame: Color,
+    /// background of the popups
+    #[serde(deserialize_with = "color")]
+    pub popup_bg: Color,
+    /// timestamps, notices, quotes and other secondary text
+    #[serde(deserialize_with = "color")]
+    pub muted: Color,
+    /// background of the highlighted words of a mention
+    #[serde(deserialize_with = "color")]
+    pub highlight: Color,
+    #[serde(deserialize_with = "color")]
+    pub highlight_text: Color,
+    /// reactions you took part in
+    #[serde(deserialize_with = "color")]
+    pub own_reaction: Color,
+    /// delivery status of your messages
+    #[serde(deserialize_with = "color")]
+    pub pending: Color,
+    #[serde(deserialize_with = "color")]
+    pub delivered: Color,
+    #[serde(deserialize_with = "color")]
+    pub failed: Color,
+    /// connection state shown in the status bar, disconnections use the failed color
+    #[serde(deserialize_with = "color")]
+    pub connected: Color,
+    #[serde(deserialize_with = "color")]
+    pub connecting: Color,
+    /// show the colors of the users, otherwise their names use the default color
+    pub user_colors: bool,
+    /// colors of the code blocks, e.g. "base16-ocean.dark", "none" leaves them uncolored
+    pub code_theme: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeBorder {
+    Plain,
+    Rounded,
+    Double,
+    Thick,
+}
+
+impl From<ThemeBorder> for BorderType {
+    fn from(border: ThemeBorder) -> Self {
+        match border {
+            ThemeBorder::Plain => BorderType::Plain,
+            ThemeBorder::Rounded => BorderType::Rounded,
+            ThemeBorder::Double => BorderType::Double,
+            ThemeBorder::Thick => BorderType::Thick,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: String::from("tron"),
+            border: ThemeBorder::Rounded,
+            accent: Color::Rgb(235, 124, 57),
+            frame: Color::LightBlue,
+            popup_bg: Color::Reset,
+            muted: Color::DarkGray,
+            highlight: Color::Yellow,
+            highlight_text: Color::Black,
+            own_reaction: Color::LightBlue,
+            pending: Color::DarkGray,
+            delivered: Color::DarkGray,
+            failed: Color::Red,
+            connected: Color::Green,
+            connecting: Color::Yellow,
+            user_colors: true,
+            code_theme: String::from("base16-ocean.dark"),
+        }
+    }
+}
+
+impl Theme {
+    /// A built-in theme or one read from `<config dir>/endl-rc/themes/<name>.toml`
+    pub fn load(name: &str) -> Result<Self, String> {
+        let theme = match name {
+            "tron" => Self::default(),
+            "high-contrast" => Self {
+                border: ThemeBorder::Thick,
+                accent: Color::Yellow,
+                frame: Color::White,
+                popup_bg: Color::Black,
+                muted: Color::Gray,
+                own_reaction: Color::LightCyan,
+                pending: Color::Gray,
+                delivered: Color::LightGreen,
+                failed: Color::LightRed,
+                connected: Color::LightGreen,
+                connecting: Color::LightYellow,
+                code_theme: String::from("base16-eighties.dark"),
+                ..Self::default()
+            },
+            "monochrome" => Self {
+                border: ThemeBorder::Plain,
+                accent: Color::White,
+                frame: Color::Gray,
+                highlight: Color::White,
+                own_reaction: Color::White,
+                delivered: Color::Gray,
+                failed: Color::White,
+                connected: Color::White,
+                connecting: Color::Gray,
+                user_colors: false,
+                code_theme: String::from(NO_HIGHLIGHTING),
+                ..Self::default()
+            },
+            name if name.contains(['/', '\\']) => {
+                return Err(format!("invalid theme name \"{}\"", name))
+            }
+            name => {
+                let path = config_dir().join(THEMES_DIR).join(format!("{}.toml", name));
+                let contents =
+                    fs::read_to_string(&path).map_err(|_| format!("unknown theme \"{}\"", name))?;
+                toml::from_str(&contents)
+                    .map_err(|e| format!("invalid theme file {}: {}", path.display(), e))?
+            }
+        };
+        if !code_themes().contains(&theme.code_theme.as_str()) {
+            return Err(format!(
+                "unknown code theme \"{}\", use one of: {}",
+                theme.code_theme,
+                code_themes().join(", ")
+            ));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            ..theme.downgraded()
+        })
+    }
+    /// Names of the built-in themes followed by the ones of the theme files
+    pub fn available() -> Vec<String> {
+        let mut names = BUILTIN_THEMES.map(String::from).to_vec();
+        if let Ok(entries) = fs::read_dir(config_dir().join(THEMES_DIR)) {
+            let mut files = entries
+                .filter_map(|entry| {
+                    let path = entry.ok()?.path();
+                    (path.extension()? == "toml")
+                        .then(|| path.file_stem()?.to_str().map(String::from))?
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            names.extend(files);
+        }
+        names
+    }
+    /// The color a user is shown with
+    pub fn user(&self, color: Color) -> Color {
+        if self.user_colors {
+            color
+        } else {
+            Color::Reset
+        }
+    }
+    /// Replaces the 24 bit colors by their closest standard colors when the terminal lacks them
+    fn downgraded(self) -> Self {
+        if supports_truecolor() {
+            return self;
+        }
+        let fit = |color| match color {
+            Color::Rgb(r, g, b) => nearest_ansi((r, g, b)),
+            color => color,
+        };
+        Self {
+            accent: fit(self.accent),
+            frame: fit(self.frame),
+            popup_bg: fit(self.popup_bg),
+            muted: fit(self.muted),
+            highlight: fit(self.highlight),
+            highlight_text: fit(self.highlight_text),
+            own_reaction: fit(self.own_reaction),
+            pending: fit(self.pending),
+            delivered: fit(self.delivered),
+            failed: fit(self.failed),
+            connected: fit(self.connected),
+            connecting: fit(self.connecting),
+            ..self
+        }
+    }
+}
+
+/// Parses colors written as names ("lightblue"), indexes ("42") or hex codes ("#ff8800")
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    Color::from_str(&color)
+        .map_err(|_| serde::de::Error::custom(format!("invalid color \"{}\"", color)))
+}